@@ -0,0 +1,571 @@
+use crate::json::map;
+use base64::Engine;
+use lib::{
+    Content, Error, ErrorKind, Event, Frame, Header, Request, Response, Trans, Value, digest,
+};
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// How a cassette treats the requests of a run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Send every request and record a new cassette
+    Record,
+    /// Answer every request from the cassette without the network
+    Replay,
+    /// Replay recorded requests and record the ones the cassette lacks
+    Auto,
+}
+
+/// Recorded request and response pairs, stored as JSON.
+pub struct Cassette {
+    path: PathBuf,
+    mode: Mode,
+    /// Lowercase names of the headers a replayed request must match
+    headers: Vec<String>,
+    interactions: Vec<Interaction>,
+    /// Which interactions have answered a request in this run
+    used: Vec<bool>,
+    /// The first interaction not yet passed through `redact`
+    unredacted: usize,
+    changed: bool,
+}
+
+struct Interaction {
+    request: Request,
+    response: Response,
+    error: Option<Error>,
+    /// Digests of the request fields that redaction changed, taken before it, by
+    /// the field name `differences` uses
+    digests: HashMap<String, String>,
+}
+
+impl Cassette {
+    /// Opens the cassette at `path`. Replaying requires the file to exist; recording
+    /// starts from an empty cassette.
+    pub async fn load(path: PathBuf, mode: Mode, headers: Vec<String>) -> Result<Self, String> {
+        let interactions = match (mode, tokio::fs::read_to_string(&path).await) {
+            (Mode::Record, _) => Vec::new(),
+            (Mode::Auto, Err(error)) if error.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            (_, Err(error)) => return Err(format!("{}: {error}", path.display())),
+            (_, Ok(text)) => {
+                parse(&text).map_err(|error| format!("{}: {error}", path.display()))?
+            }
+        };
+        Ok(Self {
+            path,
+            mode,
+            headers: headers
+                .iter()
+                .map(|header| header.to_ascii_lowercase())
+                .collect(),
+            used: vec![false; interactions.len()],
+            unredacted: interactions.len(),
+            interactions,
+            changed: mode == Mode::Record,
+        })
+    }
+
+    /// Writes the cassette back when this run recorded anything.
+    pub async fn save(&self) -> Result<(), String> {
+        if !self.changed {
+            return Ok(());
+        }
+        let interactions = self
+            .interactions
+            .iter()
+            .map(|interaction| {
+                map([
+                    ("request", request_value(&interaction.request)),
+                    (
+                        "digests",
+                        Value::Map(
+                            interaction
+                                .digests
+                                .iter()
+                                .map(|(field, digest)| {
+                                    (field.clone(), Value::String(digest.clone()))
+                                })
+                                .collect(),
+                        ),
+                    ),
+                    ("response", response_value(&interaction.response)),
+                    (
+                        "error",
+                        interaction.error.as_ref().map_or(Value::Null, |error| {
+                            map([
+                                ("kind", Value::String(error.kind.as_str().into())),
+                                ("message", Value::String(error.message.clone())),
+                            ])
+                        }),
+                    ),
+                ])
+            })
+            .collect();
+        let text = map([("interactions", Value::Array(interactions))]).to_json();
+        tokio::fs::write(&self.path, text)
+            .await
+            .map_err(|error| format!("{}: {error}", self.path.display()))
+    }
+
+    /// Passes the interactions recorded since the last call through `redact`, so
+    /// secrets never reach the file. A request field that changes keeps a digest of
+    /// its value, so the same request still matches when it is replayed.
+    pub fn redact(&mut self, redact: impl Fn(&[u8]) -> Vec<u8>) {
+        // replacing whole secrets in valid UTF-8 keeps it valid
+        let text = |text: &str| String::from_utf8_lossy(&redact(text.as_bytes())).into_owned();
+        for interaction in &mut self.interactions[self.unredacted..] {
+            let request = &mut interaction.request;
+            let mut fields = vec![("url".to_owned(), request.url.clone())];
+            for header in &request.headers {
+                let name = header.name.to_ascii_lowercase();
+                let values = header_values(&request.headers, &name);
+                fields.push((format!("header {name}"), values));
+            }
+            fields.push(("body".into(), body_text(&request.body)));
+            for (field, value) in fields {
+                if text(&value) != value {
+                    interaction.digests.insert(field, digest(&value));
+                }
+            }
+            request.url = text(&request.url);
+            for header in &mut request.headers {
+                header.value = text(&header.value);
+            }
+            if request.body.is_some() {
+                request.body = Some(Content::Text(text(&body_text(&request.body))));
+            }
+            redact_response(&mut interaction.response, &redact, &text);
+            if let Some(error) = &mut interaction.error {
+                error.message = text(&error.message);
+            }
+        }
+        self.unredacted = self.interactions.len();
+    }
+
+    /// The recorded answer to a request, preferring one that has not been used yet
+    /// so repeated requests replay in recorded order.
+    fn find(&mut self, request: &Request) -> Option<lib::Result> {
+        let matches: Vec<_> = (0..self.interactions.len())
+            .filter(|index| {
+                self.differences(&self.interactions[*index], request)
+                    .is_empty()
+            })
+            .collect();
+        let index = matches
+            .iter()
+            .find(|index| !self.used[**index])
+            .or(matches.first())
+            .copied()?;
+        self.used[index] = true;
+        let interaction = &self.interactions[index];
+        Some(lib::Result {
+            request: request.clone(),
+            response: interaction.response.clone(),
+            error: interaction.error.clone(),
+            ..Default::default()
+        })
+    }
+
+    fn record(&mut self, result: &lib::Result) {
+        self.interactions.push(Interaction {
+            request: result.request.clone(),
+            response: result.response.clone(),
+            error: result.error.clone(),
+            digests: HashMap::new(),
+        });
+        self.used.push(true);
+        self.changed = true;
+    }
+
+    /// The fields in which a sent request differs from a recorded one, as the
+    /// field name with the recorded and the sent value. A redacted field matches
+    /// when the sent value has the digest taken before redaction.
+    fn differences(
+        &self,
+        interaction: &Interaction,
+        sent: &Request,
+    ) -> Vec<(String, String, String)> {
+        let recorded = &interaction.request;
+        let mut fields = vec![
+            (
+                "method".into(),
+                recorded.method.clone(),
+                sent.method.clone(),
+            ),
+            ("url".into(), recorded.url.clone(), sent.url.clone()),
+        ];
+        for name in &self.headers {
+            fields.push((
+                format!("header {name}"),
+                header_values(&recorded.headers, name),
+                header_values(&sent.headers, name),
+            ));
+        }
+        fields.push((
+            "body".into(),
+            body_text(&recorded.body),
+            body_text(&sent.body),
+        ));
+        fields.retain(|(field, recorded, sent)| {
+            recorded != sent && interaction.digests.get(field) != Some(&digest(sent))
+        });
+        fields
+    }
+
+    /// The error for a request no interaction matches, with a diff against the
+    /// recorded request that differs in the fewest fields.
+    fn unmatched(&self, request: &Request) -> Error {
+        let mut message = format!(
+            "no recorded request matches {} {}",
+            request.method, request.url
+        );
+        let closest = self
+            .interactions
+            .iter()
+            .map(|interaction| self.differences(interaction, request))
+            .min_by_key(Vec::len);
+        match closest {
+            Some(differences) => {
+                message.push_str("; the closest recorded request differs:");
+                for (field, recorded, sent) in differences {
+                    message.push_str(&format!("\n- {field}: {recorded}\n+ {field}: {sent}"));
+                }
+            }
+            None => message.push_str("; the cassette is empty"),
+        }
+        Error::new(ErrorKind::Unmatched, message)
+    }
+}
+
+/// A transport that records to or replays from a cassette, or passes every
+/// request through when there is none.
+pub struct Vcr<T> {
+    trans: T,
+    cassette: Option<Cassette>,
+}
+
+impl<T> Vcr<T> {
+    pub fn new(trans: T, cassette: Option<Cassette>) -> Self {
+        Self { trans, cassette }
+    }
+
+    pub fn cassette(&mut self) -> Option<&mut Cassette> {
+        self.cassette.as_mut()
+    }
+}
+
+impl<T: Trans + Send> Trans for Vcr<T> {
+    fn send<'a>(
+        &'a mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = lib::Result> + Send + 'a>> {
+        Box::pin(async move {
+            let Some(cassette) = &mut self.cassette else {
+                return self.trans.send(request).await;
+            };
+            if cassette.mode != Mode::Record
+                && let Some(result) = cassette.find(&request)
+            {
+                return result;
+            }
+            if cassette.mode == Mode::Replay {
+                return lib::Result {
+                    error: Some(cassette.unmatched(&request)),
+                    request,
+                    ..Default::default()
+                };
+            }
+            let result = self.trans.send(request).await;
+            cassette.record(&result);
+            result
+        })
+    }
+
+    fn open<'a>(
+        &'a mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = (lib::Result, Option<u64>)> + Send + 'a>> {
+        if self
+            .cassette
+            .as_ref()
+            .is_some_and(|cassette| cassette.mode == Mode::Replay)
+        {
+            return Box::pin(async move {
+                let result = lib::Result {
+                    request,
+                    error: Some(Error::new(
+                        ErrorKind::Unsupported,
+                        "websockets cannot be replayed from a cassette",
+                    )),
+                    ..Default::default()
+                };
+                (result, None)
+            });
+        }
+        self.trans.open(request)
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        socket: u64,
+        frame: Frame,
+    ) -> Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'a>> {
+        self.trans.write(socket, frame)
+    }
+
+    fn read<'a>(
+        &'a mut self,
+        socket: u64,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, Error>> + Send + 'a>> {
+        self.trans.read(socket, timeout)
+    }
+
+    fn close<'a>(
+        &'a mut self,
+        socket: u64,
+        code: Option<u16>,
+        reason: String,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, Error>> + Send + 'a>> {
+        self.trans.close(socket, code, reason, timeout)
+    }
+}
+
+fn header_values(headers: &[Header], name: &str) -> String {
+    headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// The body as compared and stored: text as it is, files as `@path` and multipart
+/// parts as `name=value` pairs joined with `&`.
+fn body_text(body: &Option<Content>) -> String {
+    match body {
+        None => String::new(),
+        Some(Content::Text(text)) => text.clone(),
+        Some(Content::File(path)) => format!("@{path}"),
+        Some(Content::Parts(parts)) => parts
+            .iter()
+            .map(|part| format!("{}={}", part.name, body_text(&Some(part.content.clone()))))
+            .collect::<Vec<_>>()
+            .join("&"),
+    }
+}
+
+fn redact_response(
+    response: &mut Response,
+    redact: &impl Fn(&[u8]) -> Vec<u8>,
+    text: &impl Fn(&str) -> String,
+) {
+    response.url = text(&response.url);
+    for header in response.headers.iter_mut().chain(&mut response.trailers) {
+        header.value = text(&header.value);
+    }
+    response.body = redact(&response.body);
+    for event in &mut response.events {
+        event.data = text(&event.data);
+    }
+    for redirect in &mut response.redirects {
+        redact_response(redirect, redact, text);
+    }
+}
+
+fn headers_value(headers: &[Header]) -> Value {
+    Value::Array(
+        headers
+            .iter()
+            .map(|header| {
+                map([
+                    ("name", Value::String(header.name.clone())),
+                    ("value", Value::String(header.value.clone())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+fn request_value(request: &Request) -> Value {
+    map([
+        ("method", Value::String(request.method.clone())),
+        ("url", Value::String(request.url.clone())),
+        ("headers", headers_value(&request.headers)),
+        ("body", Value::String(body_text(&request.body))),
+    ])
+}
+
+fn response_value(response: &Response) -> Value {
+    let mut fields = HashMap::from([
+        ("url".into(), Value::String(response.url.clone())),
+        ("version".into(), Value::String(response.version.clone())),
+        ("status".into(), Value::Integer(response.status as i64)),
+        ("reason".into(), Value::String(response.reason.clone())),
+        ("headers".into(), headers_value(&response.headers)),
+        ("trailers".into(), headers_value(&response.trailers)),
+        ("encoding".into(), Value::String(response.encoding.clone())),
+        (
+            "transferred".into(),
+            Value::Integer(response.transferred as i64),
+        ),
+        (
+            "redirects".into(),
+            Value::Array(response.redirects.iter().map(response_value).collect()),
+        ),
+        (
+            "events".into(),
+            Value::Array(
+                response
+                    .events
+                    .iter()
+                    .map(|event| {
+                        map([
+                            ("id", Value::String(event.id.clone())),
+                            ("event", Value::String(event.event.clone())),
+                            ("data", Value::String(event.data.clone())),
+                            (
+                                "retry",
+                                event
+                                    .retry
+                                    .map_or(Value::Null, |retry| Value::Integer(retry as i64)),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+    ]);
+    match String::from_utf8(response.body.clone()) {
+        Ok(body) => fields.insert("body".into(), Value::String(body)),
+        Err(_) => fields.insert(
+            "body_base64".into(),
+            Value::String(base64::engine::general_purpose::STANDARD.encode(&response.body)),
+        ),
+    };
+    Value::Map(fields)
+}
+
+fn parse(text: &str) -> Result<Vec<Interaction>, String> {
+    let cassette = Value::from_json(text)?;
+    array(field(&cassette, "interactions")?)?
+        .iter()
+        .map(|interaction| {
+            let request = field(interaction, "request")?;
+            let body = string(request, "body")?;
+            let error = match field(interaction, "error")? {
+                Value::Null => None,
+                error => Some(Error::new(
+                    string(error, "kind")?.parse()?,
+                    string(error, "message")?,
+                )),
+            };
+            Ok(Interaction {
+                request: Request {
+                    method: string(request, "method")?,
+                    url: string(request, "url")?,
+                    headers: headers(request, "headers")?,
+                    body: (!body.is_empty()).then_some(Content::Text(body)),
+                    ..Default::default()
+                },
+                response: response(field(interaction, "response")?)?,
+                error,
+                // cassettes recorded before digests were kept have none
+                digests: match field(interaction, "digests") {
+                    Ok(Value::Map(digests)) => digests
+                        .iter()
+                        .map(|(field, digest)| (field.clone(), digest.to_string()))
+                        .collect(),
+                    _ => HashMap::new(),
+                },
+            })
+        })
+        .collect()
+}
+
+fn response(value: &Value) -> Result<Response, String> {
+    let body = match field(value, "body_base64") {
+        Ok(_) => base64::engine::general_purpose::STANDARD
+            .decode(string(value, "body_base64")?)
+            .map_err(|error| format!("invalid body_base64: {error}"))?,
+        Err(_) => string(value, "body")?.into_bytes(),
+    };
+    let events = array(field(value, "events")?)?
+        .iter()
+        .map(|event| {
+            Ok(Event {
+                id: string(event, "id")?,
+                event: string(event, "event")?,
+                data: string(event, "data")?,
+                retry: match field(event, "retry")? {
+                    Value::Integer(retry) => Some(*retry as u64),
+                    _ => None,
+                },
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Response {
+        url: string(value, "url")?,
+        version: string(value, "version")?,
+        status: integer(value, "status")? as u16,
+        reason: string(value, "reason")?,
+        headers: headers(value, "headers")?,
+        body,
+        trailers: headers(value, "trailers")?,
+        encoding: string(value, "encoding")?,
+        transferred: integer(value, "transferred")? as usize,
+        redirects: array(field(value, "redirects")?)?
+            .iter()
+            .map(response)
+            .collect::<Result<_, _>>()?,
+        events,
+        ..Default::default()
+    })
+}
+
+fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, String> {
+    match value {
+        Value::Map(fields) => fields
+            .get(name)
+            .ok_or_else(|| format!("missing field '{name}'")),
+        _ => Err(format!("expected an object with field '{name}'")),
+    }
+}
+
+fn string(value: &Value, name: &str) -> Result<String, String> {
+    match field(value, name)? {
+        Value::String(text) => Ok(text.clone()),
+        _ => Err(format!("field '{name}' must be a string")),
+    }
+}
+
+fn integer(value: &Value, name: &str) -> Result<i64, String> {
+    match field(value, name)? {
+        Value::Integer(integer) => Ok(*integer),
+        _ => Err(format!("field '{name}' must be an integer")),
+    }
+}
+
+fn array(value: &Value) -> Result<&[Value], String> {
+    match value {
+        Value::Array(values) => Ok(values),
+        _ => Err("expected an array".into()),
+    }
+}
+
+fn headers(value: &Value, name: &str) -> Result<Vec<Header>, String> {
+    array(field(value, name)?)?
+        .iter()
+        .map(|header| {
+            Ok(Header {
+                name: string(header, "name")?,
+                value: string(header, "value")?,
+            })
+        })
+        .collect()
+}
@@ -1,16 +1,21 @@
-use lib::{Mech, Parser, Request, Source, Trans, Value};
+use crate::cassette::{Cassette, Mode, Vcr};
+use crate::har::{Har, Recorder};
+use crate::loader::Loader;
+use lib::{Frame, Mech, Parser, Request, Source, Trans, Value};
 use std::collections::HashMap;
 use std::env::current_dir;
-use std::ffi::OsStr;
 use std::future::Future;
 use std::path::PathBuf;
 use std::pin::Pin;
-use tokio::fs::{read, read_dir};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::write;
 use tokio::io::{AsyncBufReadExt, BufReader, stdin};
 
 pub async fn repl() {
     let mut lines = BufReader::new(stdin()).lines();
     let mut source = Source::new();
+    // one loader for the session, so a file imported again is not merged twice
+    let mut loader = Loader::default();
     let mut values = HashMap::new();
     let mut buffer = Vec::new();
     while let Ok(Some(line)) = lines.next_line().await {
@@ -21,7 +26,7 @@ pub async fn repl() {
         let text = buffer.join("\n");
         match source_state(&text) {
             State::Complete | State::Invalid => {
-                values = eval(text, &mut source, values).await;
+                values = eval(text, &mut source, &mut loader, values).await;
                 buffer.clear();
             }
             State::Incomplete => {}
@@ -32,14 +37,25 @@ pub async fn repl() {
 pub async fn eval(
     text: String,
     source: &mut Source,
+    loader: &mut Loader,
     values: HashMap<String, Value>,
 ) -> HashMap<String, Value> {
-    match Parser::new(&text).parse() {
+    let parsed = match Parser::new(&text).parse() {
+        Ok(parsed) => match current_dir() {
+            Ok(base) => loader
+                .imports(&base, &parsed, "", source)
+                .await
+                .map(|_| parsed),
+            Err(error) => Err(error.to_string()),
+        },
+        Err(error) => Err(error),
+    };
+    match parsed {
         Ok(parsed) => {
             let index = source.extend(parsed);
             let expressions = source.exprs[index..].to_vec();
             let mut mech = Mech::interactive(source).with_values(values);
-            let mut trans = HttpTrans;
+            let mut trans = HttpTrans::default();
             match mech.run(&expressions, &mut trans).await {
                 Ok(value) => println!("{value}"),
                 Err(error) => println!("{error}"),
@@ -53,7 +69,15 @@ pub async fn eval(
     }
 }
 
-pub async fn test(target: Option<String>, env: Option<String>, path: Option<PathBuf>) -> bool {
+pub async fn test(
+    target: Option<String>,
+    env: Option<String>,
+    path: Option<PathBuf>,
+    har: Option<PathBuf>,
+    cassette: Option<PathBuf>,
+    mode: Mode,
+    headers: Vec<String>,
+) -> bool {
     let (name, tag) = match target {
         Some(target) => match target.strip_prefix('@') {
             Some("") => {
@@ -66,14 +90,7 @@ pub async fn test(target: Option<String>, env: Option<String>, path: Option<Path
         None => (None, None),
     };
     let path = path.unwrap_or_else(|| current_dir().unwrap());
-    let text = match read_sources(path).await {
-        Ok(text) => text,
-        Err(error) => {
-            println!("{error}");
-            return false;
-        }
-    };
-    let source = match Parser::new(&text).parse() {
+    let source = match Loader::load(path).await {
         Ok(source) => source,
         Err(error) => {
             println!("{error}");
@@ -101,11 +118,34 @@ pub async fn test(target: Option<String>, env: Option<String>, path: Option<Path
             .cloned()
             .collect(),
     };
-    let mut trans = HttpTrans;
+    let cassette = match cassette {
+        Some(path) => match Cassette::load(path, mode, headers).await {
+            Ok(cassette) => Some(cassette),
+            Err(error) => {
+                println!("{error}");
+                return false;
+            }
+        },
+        None => None,
+    };
+    let mut trans = Recorder::new(Vcr::new(HttpTrans::default(), cassette));
+    let mut archive = Har::default();
     let mut passed = true;
     for name in names {
+        let started = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
         let result = match Mech::new(&source, env.as_deref()) {
-            Ok(mut mech) => mech.run_test(&name, &mut trans).await,
+            Ok(mut mech) => {
+                let result = mech.run_test(&name, &mut trans).await;
+                archive.page(&name, started, trans.take(), |bytes| {
+                    mech.redact_bytes(bytes)
+                });
+                if let Some(cassette) = trans.trans().cassette() {
+                    cassette.redact(|bytes| mech.redact_bytes(bytes));
+                }
+                result
+            }
             Err(error) => Err(error),
         };
         match result {
@@ -116,16 +156,97 @@ pub async fn test(target: Option<String>, env: Option<String>, path: Option<Path
             }
         }
     }
+    if let Some(har) = har
+        && let Err(error) = write(&har, archive.to_json()).await
+    {
+        println!("{}: {error}", har.display());
+        return false;
+    }
+    if let Some(cassette) = trans.trans().cassette()
+        && let Err(error) = cassette.save().await
+    {
+        println!("{error}");
+        return false;
+    }
     passed
 }
 
-struct HttpTrans;
+#[derive(Default)]
+struct HttpTrans {
+    client: http::Client,
+    sockets: HashMap<u64, http::WebSocket>,
+    next: u64,
+}
+
+impl HttpTrans {
+    fn socket(&mut self, socket: u64) -> Result<&mut http::WebSocket, lib::Error> {
+        self.sockets
+            .get_mut(&socket)
+            .ok_or_else(|| lib::Error::new(lib::ErrorKind::Closed, "websocket closed"))
+    }
+}
+
 impl Trans for HttpTrans {
     fn send<'a>(
         &'a mut self,
         request: Request,
     ) -> Pin<Box<dyn Future<Output = lib::Result> + Send + 'a>> {
-        Box::pin(http::send(request))
+        Box::pin(self.client.send(request))
+    }
+
+    fn open<'a>(
+        &'a mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = (lib::Result, Option<u64>)> + Send + 'a>> {
+        Box::pin(async move {
+            let (result, socket) = self.client.open(request).await;
+            let socket = socket.map(|socket| {
+                self.next += 1;
+                self.sockets.insert(self.next, socket);
+                self.next
+            });
+            (result, socket)
+        })
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        socket: u64,
+        frame: Frame,
+    ) -> Pin<Box<dyn Future<Output = Result<(), lib::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let socket = self.socket(socket)?;
+            socket.send(frame).await.map_err(lib::Error::from)
+        })
+    }
+
+    fn read<'a>(
+        &'a mut self,
+        socket: u64,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, lib::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let socket = self.socket(socket)?;
+            socket.receive(timeout).await.map_err(lib::Error::from)
+        })
+    }
+
+    fn close<'a>(
+        &'a mut self,
+        socket: u64,
+        code: Option<u16>,
+        reason: String,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, lib::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let result = self
+                .socket(socket)?
+                .close(code, reason, timeout)
+                .await
+                .map_err(lib::Error::from);
+            self.sockets.remove(&socket);
+            result
+        })
     }
 }
 
@@ -166,31 +287,3 @@ fn source_state(text: &str) -> State {
         State::Incomplete
     }
 }
-
-async fn read_sources(path: PathBuf) -> Result<String, String> {
-    let mut bytes = Vec::new();
-    read_source_bytes(path, &mut bytes).await?;
-    String::from_utf8(bytes).map_err(|error| error.to_string())
-}
-
-async fn read_source_bytes(path: PathBuf, bytes: &mut Vec<u8>) -> Result<(), String> {
-    if path.is_dir() {
-        let mut entries = read_dir(&path).await.map_err(|error| error.to_string())?;
-        let mut paths = Vec::new();
-        while let Some(entry) = entries
-            .next_entry()
-            .await
-            .map_err(|error| error.to_string())?
-        {
-            paths.push(entry.path());
-        }
-        paths.sort();
-        for path in paths {
-            Box::pin(read_source_bytes(path, bytes)).await?;
-        }
-    } else if path.extension().and_then(OsStr::to_str) == Some("fan") {
-        bytes.extend(read(&path).await.map_err(|error| error.to_string())?);
-        bytes.push(b'\n');
-    }
-    Ok(())
-}
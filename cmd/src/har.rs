@@ -0,0 +1,358 @@
+use crate::json::map;
+use base64::Engine;
+use lib::{Content, Frame, Header, Request, Response, Timing, Trans, Value};
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+/// A transport that keeps every result passing through it for the archive.
+pub struct Recorder<T> {
+    trans: T,
+    results: Vec<lib::Result>,
+}
+
+impl<T> Recorder<T> {
+    pub fn new(trans: T) -> Self {
+        Self {
+            trans,
+            results: Vec::new(),
+        }
+    }
+
+    pub fn trans(&mut self) -> &mut T {
+        &mut self.trans
+    }
+
+    /// Returns the results recorded since the last call.
+    pub fn take(&mut self) -> Vec<lib::Result> {
+        std::mem::take(&mut self.results)
+    }
+}
+
+impl<T: Trans + Send> Trans for Recorder<T> {
+    fn send<'a>(
+        &'a mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = lib::Result> + Send + 'a>> {
+        Box::pin(async move {
+            let result = self.trans.send(request).await;
+            self.results.push(result.clone());
+            result
+        })
+    }
+
+    fn open<'a>(
+        &'a mut self,
+        request: Request,
+    ) -> Pin<Box<dyn Future<Output = (lib::Result, Option<u64>)> + Send + 'a>> {
+        Box::pin(async move {
+            let (result, socket) = self.trans.open(request).await;
+            self.results.push(result.clone());
+            (result, socket)
+        })
+    }
+
+    fn write<'a>(
+        &'a mut self,
+        socket: u64,
+        frame: Frame,
+    ) -> Pin<Box<dyn Future<Output = Result<(), lib::Error>> + Send + 'a>> {
+        self.trans.write(socket, frame)
+    }
+
+    fn read<'a>(
+        &'a mut self,
+        socket: u64,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, lib::Error>> + Send + 'a>> {
+        self.trans.read(socket, timeout)
+    }
+
+    fn close<'a>(
+        &'a mut self,
+        socket: u64,
+        code: Option<u16>,
+        reason: String,
+        timeout: Duration,
+    ) -> Pin<Box<dyn Future<Output = Result<Frame, lib::Error>> + Send + 'a>> {
+        self.trans.close(socket, code, reason, timeout)
+    }
+}
+
+/// An HTTP Archive (HAR 1.2) with one page per test.
+#[derive(Default)]
+pub struct Har {
+    pages: Vec<Value>,
+    entries: Vec<Value>,
+}
+
+impl Har {
+    /// Adds a page for a test and an entry for every response it received,
+    /// redirects included, with the request that was sent for it. Everything
+    /// recorded, binary bodies included, is passed through `redact`.
+    pub fn page(
+        &mut self,
+        title: &str,
+        started: Duration,
+        results: Vec<lib::Result>,
+        redact: impl Fn(&[u8]) -> Vec<u8>,
+    ) {
+        let id = format!("page_{}", self.pages.len() + 1);
+        self.pages.push(map([
+            ("startedDateTime", Value::String(date(started))),
+            ("id", Value::String(id.clone())),
+            ("title", Value::String(title.into())),
+            (
+                "pageTimings",
+                map([
+                    ("onContentLoad", Value::Integer(-1)),
+                    ("onLoad", Value::Integer(-1)),
+                ]),
+            ),
+        ]));
+        for result in results {
+            // a transport that does not report the requests it followed with is
+            // recorded with the original request for every hop
+            let request = |hop: usize| match hop {
+                0 => &result.request,
+                hop => result.followed.get(hop - 1).unwrap_or(&result.request),
+            };
+            let mut url = result.request.url.clone();
+            for (hop, response) in result.response.redirects.iter().enumerate() {
+                let timing = Timing {
+                    start: result.timing.start,
+                    ..Default::default()
+                };
+                let entry = entry(&id, request(hop), &url, response, &timing, None, &redact);
+                self.entries.push(entry);
+                if !response.url.is_empty() {
+                    url = response.url.clone();
+                }
+            }
+            if !result.response.url.is_empty() {
+                url = result.response.url.clone();
+            }
+            let error = result.error.as_ref().map(|error| error.message.as_str());
+            let entry = entry(
+                &id,
+                request(result.response.redirects.len()),
+                &url,
+                &result.response,
+                &result.timing,
+                error,
+                &redact,
+            );
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let creator = map([
+            ("name", Value::String(env!("CARGO_BIN_NAME").into())),
+            ("version", Value::String(env!("CARGO_PKG_VERSION").into())),
+        ]);
+        map([(
+            "log",
+            map([
+                ("version", Value::String("1.2".into())),
+                ("creator", creator),
+                ("pages", Value::Array(self.pages.clone())),
+                ("entries", Value::Array(self.entries.clone())),
+            ]),
+        )])
+        .to_json()
+    }
+}
+
+fn entry(
+    page: &str,
+    request: &Request,
+    url: &str,
+    response: &Response,
+    timing: &Timing,
+    error: Option<&str>,
+    redact_bytes: &impl Fn(&[u8]) -> Vec<u8>,
+) -> Value {
+    // replacing whole secrets in valid UTF-8 keeps it valid
+    let redact = &|text: &str| String::from_utf8_lossy(&redact_bytes(text.as_bytes())).into_owned();
+    let millis = |duration: Duration| Value::Float(duration.as_secs_f64() * 1000.0);
+    let version = match (response.version.as_str(), request.version.as_str()) {
+        ("", "") => "HTTP/1.1",
+        ("", version) | (version, _) => version,
+    };
+    let query = url
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default())
+        .filter(|query| !query.is_empty())
+        .map(|query| {
+            query
+                .split('&')
+                .map(|pair| {
+                    let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                    pair_value(name, value, redact)
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let mut har_request = HashMap::from([
+        ("method".into(), Value::String(request.method.clone())),
+        ("url".into(), Value::String(redact(url))),
+        ("httpVersion".into(), Value::String(version.into())),
+        ("cookies".into(), Value::Array(Vec::new())),
+        ("headers".into(), headers(&request.headers, redact)),
+        ("queryString".into(), Value::Array(query)),
+        ("headersSize".into(), Value::Integer(-1)),
+        ("bodySize".into(), Value::Integer(-1)),
+    ]);
+    if let Some(body) = &request.body {
+        let mime = header(&request.headers, "content-type").unwrap_or_default();
+        let mut data = HashMap::from([("mimeType".into(), Value::String(mime.into()))]);
+        match body {
+            Content::Text(text) => {
+                har_request.insert("bodySize".into(), Value::Integer(text.len() as i64));
+                data.insert("text".into(), Value::String(redact(text)));
+            }
+            Content::File(path) => {
+                data.insert("text".into(), Value::String(String::new()));
+                data.insert("comment".into(), Value::String(redact(&format!("@{path}"))));
+            }
+            Content::Parts(parts) => {
+                let params = parts
+                    .iter()
+                    .map(|part| {
+                        let mut param =
+                            HashMap::from([("name".into(), Value::String(redact(&part.name)))]);
+                        match &part.content {
+                            Content::Text(text) => {
+                                param.insert("value".into(), Value::String(redact(text)));
+                            }
+                            Content::File(path) => {
+                                let value = redact(&format!("@{path}"));
+                                param.insert("value".into(), Value::String(value));
+                            }
+                            Content::Parts(_) => {}
+                        }
+                        if let Some(filename) = &part.filename {
+                            param.insert("fileName".into(), Value::String(redact(filename)));
+                        }
+                        if let Some(content_type) = &part.content_type {
+                            let content_type = Value::String(redact(content_type));
+                            param.insert("contentType".into(), content_type);
+                        }
+                        Value::Map(param)
+                    })
+                    .collect();
+                data.insert("params".into(), Value::Array(params));
+            }
+        }
+        har_request.insert("postData".into(), Value::Map(data));
+    }
+    let mut content = HashMap::from([
+        ("size".into(), Value::Integer(response.body.len() as i64)),
+        (
+            "mimeType".into(),
+            Value::String(
+                header(&response.headers, "content-type")
+                    .unwrap_or_default()
+                    .into(),
+            ),
+        ),
+    ]);
+    match std::str::from_utf8(&response.body) {
+        Ok(text) => {
+            content.insert("text".into(), Value::String(redact(text)));
+        }
+        Err(_) => {
+            let body = redact_bytes(&response.body);
+            let text = base64::engine::general_purpose::STANDARD.encode(body);
+            content.insert("text".into(), Value::String(text));
+            content.insert("encoding".into(), Value::String("base64".into()));
+        }
+    }
+    let har_response = map([
+        ("status", Value::Integer(response.status as i64)),
+        ("statusText", Value::String(response.reason.clone())),
+        ("httpVersion", Value::String(response.version.clone())),
+        ("cookies", Value::Array(Vec::new())),
+        ("headers", headers(&response.headers, redact)),
+        ("content", Value::Map(content)),
+        (
+            "redirectURL",
+            Value::String(redact(
+                header(&response.headers, "location").unwrap_or_default(),
+            )),
+        ),
+        ("headersSize", Value::Integer(-1)),
+        ("bodySize", Value::Integer(response.transferred as i64)),
+    ]);
+    let timings = map([
+        ("blocked", Value::Integer(-1)),
+        ("dns", millis(timing.resolve)),
+        ("connect", millis(timing.connect)),
+        ("ssl", Value::Integer(-1)),
+        ("send", millis(timing.write)),
+        ("wait", millis(timing.delay)),
+        ("receive", millis(timing.read)),
+    ]);
+    let mut entry = HashMap::from([
+        ("pageref".into(), Value::String(page.into())),
+        ("startedDateTime".into(), Value::String(date(timing.start))),
+        ("time".into(), millis(timing.total)),
+        ("request".into(), Value::Map(har_request)),
+        ("response".into(), har_response),
+        ("cache".into(), Value::Map(HashMap::new())),
+        ("timings".into(), timings),
+    ]);
+    if let Some(error) = error {
+        entry.insert("_error".into(), Value::String(redact(error)));
+    }
+    Value::Map(entry)
+}
+
+fn pair_value(name: &str, value: &str, redact: &impl Fn(&str) -> String) -> Value {
+    map([
+        ("name", Value::String(redact(name))),
+        ("value", Value::String(redact(value))),
+    ])
+}
+
+fn headers(headers: &[Header], redact: &impl Fn(&str) -> String) -> Value {
+    Value::Array(
+        headers
+            .iter()
+            .map(|header| pair_value(&header.name, &header.value, redact))
+            .collect(),
+    )
+}
+
+fn header<'a>(headers: &'a [Header], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case(name))
+        .map(|header| header.value.as_str())
+}
+
+/// Formats time since the unix epoch as an ISO 8601 date in UTC with milliseconds.
+fn date(time: Duration) -> String {
+    let seconds = time.as_secs();
+    let (days, rest) = (seconds / 86400, seconds % 86400);
+    // the civil date from days, after Howard Hinnant's algorithm
+    let days = days + 719468;
+    let era = days / 146097;
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60,
+        time.subsec_millis()
+    )
+}
@@ -0,0 +1,12 @@
+use lib::Value;
+
+/// Builds a JSON object from its fields, for the documents that HAR files and
+/// cassettes are written as.
+pub fn map<const N: usize>(fields: [(&str, Value); N]) -> Value {
+    Value::Map(
+        fields
+            .into_iter()
+            .map(|(name, value)| (name.to_owned(), value))
+            .collect(),
+    )
+}
@@ -0,0 +1,106 @@
+use lib::{Parser, Source};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::fs::{canonicalize, read_dir, read_to_string};
+
+/// Reads `.fan` files into one source, following the imports each file declares.
+#[derive(Default)]
+pub struct Loader {
+    /// Files being read, the outermost first, for finding import cycles
+    stack: Vec<PathBuf>,
+    /// Files already merged, with the namespace they were merged under
+    merged: HashSet<(PathBuf, String)>,
+}
+
+impl Loader {
+    /// Loads every `.fan` file under a path in path order. Each file is parsed on
+    /// its own and merged, so a file imported more than once is only merged once.
+    pub async fn load(path: PathBuf) -> Result<Source, String> {
+        let mut paths = Vec::new();
+        collect(path, &mut paths).await?;
+        let mut loader = Self::default();
+        let mut source = Source::new();
+        for path in paths {
+            loader.file(&path, String::new(), &mut source).await?;
+        }
+        Ok(source)
+    }
+
+    /// Merges the files imported by `parsed` into `source`, resolving their paths
+    /// against `base`, the directory of the importing file.
+    pub async fn imports(
+        &mut self,
+        base: &Path,
+        parsed: &Source,
+        namespace: &str,
+        source: &mut Source,
+    ) -> Result<(), String> {
+        for import in &parsed.imports {
+            let namespace = match (namespace, &import.alias) {
+                (namespace, None) => namespace.to_owned(),
+                ("", Some(alias)) => alias.clone(),
+                (namespace, Some(alias)) => format!("{namespace}.{alias}"),
+            };
+            Box::pin(self.file(&base.join(&import.path), namespace, source)).await?;
+        }
+        Ok(())
+    }
+
+    async fn file(
+        &mut self,
+        path: &Path,
+        namespace: String,
+        source: &mut Source,
+    ) -> Result<(), String> {
+        let location = |error: String| format!("{}: {error}", path.display());
+        let canonical = canonicalize(path)
+            .await
+            .map_err(|error| location(error.to_string()))?;
+        if self.stack.contains(&canonical) {
+            let cycle = self
+                .stack
+                .iter()
+                .skip_while(|file| **file != canonical)
+                .chain([&canonical])
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>();
+            return Err(format!("import cycle: {}", cycle.join(" -> ")));
+        }
+        if !self.merged.insert((canonical.clone(), namespace.clone())) {
+            return Ok(());
+        }
+        let text = read_to_string(path)
+            .await
+            .map_err(|error| location(error.to_string()))?;
+        let parsed = Parser::named(&path.display().to_string(), &text).parse()?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        self.stack.push(canonical);
+        let imported = self.imports(base, &parsed, &namespace, source).await;
+        self.stack.pop();
+        imported?;
+        let file = path.display().to_string();
+        source.merge(parsed, &namespace, &file).map_err(location)
+    }
+}
+
+async fn collect(path: PathBuf, paths: &mut Vec<PathBuf>) -> Result<(), String> {
+    if path.is_dir() {
+        let mut entries = read_dir(&path).await.map_err(|error| error.to_string())?;
+        let mut children = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|error| error.to_string())?
+        {
+            children.push(entry.path());
+        }
+        children.sort();
+        for path in children {
+            Box::pin(collect(path, paths)).await?;
+        }
+    } else if path.extension().and_then(OsStr::to_str) == Some("fan") {
+        paths.push(path);
+    }
+    Ok(())
+}
@@ -1,5 +1,10 @@
+mod cassette;
 mod command;
-use clap::{Parser, Subcommand};
+mod har;
+mod json;
+mod loader;
+use cassette::Mode;
+use clap::{Args, Parser, Subcommand};
 use lib::Source;
 use std::collections::HashMap;
 use std::path::PathBuf;
@@ -31,18 +36,65 @@ enum Commands {
         /// Path
         #[arg(short, long)]
         path: Option<PathBuf>,
+        /// Write every request and response to this HAR file
+        #[arg(long)]
+        har: Option<PathBuf>,
+        /// Record requests to or replay them from this cassette file
+        #[arg(long)]
+        cassette: Option<PathBuf>,
+        #[command(flatten)]
+        mode: Modes,
+        /// A header that replayed requests must match, besides method, url and body
+        #[arg(long = "match-header", requires = "cassette")]
+        match_headers: Vec<String>,
     },
 }
 
+/// The cassette mode flags, of which at most one may be given.
+#[derive(Args)]
+#[group(multiple = false, requires = "cassette")]
+struct Modes {
+    /// Send every request and record a new cassette
+    #[arg(long)]
+    record: bool,
+    /// Answer every request from the cassette, failing unmatched ones
+    #[arg(long)]
+    replay: bool,
+    /// Replay recorded requests and record the rest (the default)
+    #[arg(long)]
+    auto: bool,
+}
+
+impl From<Modes> for Mode {
+    fn from(modes: Modes) -> Self {
+        match (modes.record, modes.replay, modes.auto) {
+            (true, _, _) => Mode::Record,
+            (_, true, _) => Mode::Replay,
+            // --auto names the default explicitly
+            (_, _, true) | (false, false, false) => Mode::Auto,
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let interface = Interface::parse();
     match interface.command {
         Some(Commands::Eval { text }) => {
-            let _ = command::eval(text, &mut Source::new(), HashMap::new()).await;
+            let mut loader = loader::Loader::default();
+            let _ = command::eval(text, &mut Source::new(), &mut loader, HashMap::new()).await;
         }
-        Some(Commands::Test { target, env, path }) => {
-            if !command::test(target, env, path).await {
+        Some(Commands::Test {
+            target,
+            env,
+            path,
+            har,
+            cassette,
+            mode,
+            match_headers,
+        }) => {
+            let mode = Mode::from(mode);
+            if !command::test(target, env, path, har, cassette, mode, match_headers).await {
                 std::process::exit(1);
             }
         }
@@ -108,6 +108,32 @@ async fn repl_recovers_after_invalid_delimiter() -> Result<(), Box<dyn std::erro
     Ok(())
 }
 
+#[tokio::test]
+async fn repl_merges_each_imported_file_once() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    temp.child("common.fan")
+        .write_str(r#"fn version() { "v1" }"#)?;
+    temp.child("a.fan").write_str(r#"import "common.fan""#)?;
+    temp.child("b.fan").write_str(r#"import "common.fan""#)?;
+    let mut child = command()
+        .current_dir(temp.path())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"import \"a.fan\"\nimport \"b.fan\"\nimport \"a.fan\"\nversion()\nexit\n")
+        .await?;
+    let output = child.wait_with_output().await?;
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(!stdout.contains("duplicate"), "{stdout}");
+    assert!(stdout.ends_with("v1\n"), "{stdout}");
+    Ok(())
+}
+
 #[tokio::test]
 async fn cli_fails_for_false_expectation_and_unknown_test() -> Result<(), Box<dyn std::error::Error>>
 {
@@ -121,7 +147,13 @@ async fn cli_fails_for_false_expectation_and_unknown_test() -> Result<(), Box<dy
         .output()
         .await?;
     assert!(!failed.status.success());
-    assert!(String::from_utf8_lossy(&failed.stdout).contains("expectation failed"));
+    let stdout = String::from_utf8(failed.stdout)?;
+    assert!(stdout.contains("expectation failed"), "{stdout}");
+    assert!(stdout.contains("tests.fan:1:16"), "{stdout}");
+    assert!(
+        stdout.contains("| test failing { expect false; }"),
+        "{stdout}"
+    );
 
     let missing = command()
         .arg("test")
@@ -158,6 +190,88 @@ async fn cli_loads_fan_files_recursively_and_ignores_other_files()
     Ok(())
 }
 
+#[tokio::test]
+async fn cli_resolves_imports_relative_to_the_importing_file()
+-> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+    temp.child("common/auth.fan").write_str(
+        r#"fn token(user: string) { return "\(kind())-\(user)-\(version())"; }
+        fn kind() { "bearer" }"#,
+    )?;
+    temp.child("common/base.fan")
+        .write_str(r#"fn version() { "v1" }"#)?;
+    temp.child("tests/a.fan").write_str(
+        r#"import "../common/auth.fan" as auth
+        import "../common/base.fan"
+        test first { expect auth.token("gauss") == "bearer-gauss-v1"; expect version() == "v1"; }"#,
+    )?;
+    temp.child("tests/nested/b.fan").write_str(
+        r#"import "../../common/base.fan"
+        test second { expect version() == "v1"; }"#,
+    )?;
+    let output = command()
+        .arg("test")
+        .arg("--path")
+        .arg(temp.child("tests").path())
+        .output()
+        .await?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(output.status.success(), "{stdout}");
+    assert!(stdout.contains("first"));
+    assert!(stdout.contains("second"));
+
+    temp.child("clash/one/t.fan")
+        .write_str("test same { expect true; }")?;
+    temp.child("clash/two/t.fan")
+        .write_str("test same { expect true; }")?;
+    let output = command()
+        .arg("test")
+        .arg("--path")
+        .arg(temp.child("clash").path())
+        .output()
+        .await?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("duplicate test 'same', also declared in "),
+        "{stdout}"
+    );
+    assert!(stdout.contains("one/t.fan"), "{stdout}");
+    assert!(stdout.contains("two/t.fan"), "{stdout}");
+
+    temp.child("aliased/lib.fan")
+        .write_str("test hidden { expect false; }")?;
+    temp.child("aliased/main.fan")
+        .write_str(r#"import "lib.fan" as lib test main { expect true; }"#)?;
+    let output = command()
+        .arg("test")
+        .arg("--path")
+        .arg(temp.child("aliased/main.fan").path())
+        .output()
+        .await?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(
+        stdout.contains("tests cannot be imported as 'lib'"),
+        "{stdout}"
+    );
+
+    temp.child("cycle/x.fan")
+        .write_str(r#"import "y.fan" test x { expect true; }"#)?;
+    temp.child("cycle/y.fan").write_str(r#"import "x.fan""#)?;
+    let output = command()
+        .arg("test")
+        .arg("--path")
+        .arg(temp.child("cycle").path())
+        .output()
+        .await?;
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("import cycle: "), "{stdout}");
+    assert!(stdout.contains("x.fan -> "), "{stdout}");
+    Ok(())
+}
+
 #[tokio::test]
 async fn cli_runs_dsl_against_a_real_http_server() -> Result<(), Box<dyn std::error::Error>> {
     use axum::Json;
@@ -212,6 +326,243 @@ async fn cli_runs_dsl_against_a_real_http_server() -> Result<(), Box<dyn std::er
     Ok(())
 }
 
+#[tokio::test]
+async fn cli_writes_har_archive_with_secrets_redacted() -> Result<(), Box<dyn std::error::Error>> {
+    use axum::Router;
+    use axum::http::HeaderMap;
+    use axum::http::StatusCode;
+    use axum::http::header::LOCATION;
+    use axum::routing::{get, post};
+    use base64::Engine;
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+    let port = listener.local_addr()?.port();
+    let app = Router::new()
+        .route(
+            "/token",
+            get(|headers: HeaderMap| async move {
+                let authorization = headers["authorization"].to_str().unwrap_or_default();
+                format!("seen {authorization}")
+            }),
+        )
+        .route(
+            "/binary",
+            get(|headers: HeaderMap| async move {
+                let mut body = vec![0xff, 0xfe];
+                body.extend_from_slice(headers["authorization"].as_bytes());
+                body
+            }),
+        )
+        .route(
+            "/moved",
+            post(|| async { (StatusCode::SEE_OTHER, [(LOCATION, "/token")]) }),
+        );
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+    let closed = tokio::net::TcpListener::bind(("127.0.0.1", 0))
+        .await?
+        .local_addr()?
+        .port();
+
+    let temp = assert_fs::TempDir::new()?;
+    temp.child("scenario.fan").write_str(&format!(
+        r#"api service {{
+            scheme: http,
+            host: "127.0.0.1",
+            port: {port},
+            headers: {{ Authorization: "Bearer \(secret("HAR_TOKEN"))" }},
+            token() {{ method: GET, path: "/token?page=1" }},
+            blob() {{ method: GET, path: "/binary" }},
+            moved() {{
+                method: POST,
+                path: "/moved",
+                headers: {{ "Content-Type": "text/plain" }},
+                text: "moving",
+                follow: true
+            }}
+        }}
+        api closed {{
+            scheme: http,
+            host: "127.0.0.1",
+            port: {closed},
+            ping() {{ method: GET, path: "/ping" }}
+        }}
+        test first {{
+            expect service.token().status == 200;
+            expect service.blob().status == 200;
+            expect service.moved().status == 200;
+        }}
+        test second {{ expect try(closed.ping()).error.kind == "refused"; }}"#,
+    ))?;
+    let har = temp.child("run.har");
+    let output = command()
+        .arg("test")
+        .arg("--path")
+        .arg(temp.path())
+        .arg("--har")
+        .arg(har.path())
+        .env("HAR_TOKEN", "hidden-token")
+        .output()
+        .await?;
+    server.abort();
+    assert!(
+        output.status.success(),
+        "stdout: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    let text = std::fs::read_to_string(har.path())?;
+    assert!(!text.contains("hidden-token"), "{text}");
+    let log = &serde_json::from_str::<serde_json::Value>(&text)?["log"];
+    assert_eq!(log["version"], "1.2");
+    let pages = log["pages"].as_array().unwrap();
+    let titles: Vec<_> = pages.iter().map(|page| page["title"].clone()).collect();
+    assert_eq!(titles.len(), 2);
+    assert!(titles.contains(&"first".into()) && titles.contains(&"second".into()));
+    let page =
+        |title: &str| pages.iter().find(|page| page["title"] == title).unwrap()["id"].clone();
+    let entries = |title: &str| {
+        let page = page(title);
+        let entries = log["entries"].as_array().unwrap().iter();
+        entries
+            .filter(|entry| entry["pageref"] == page)
+            .collect::<Vec<_>>()
+    };
+    let [token, blob, moved, followed] = entries("first")[..] else {
+        panic!("{text}");
+    };
+    let [refused] = entries("second")[..] else {
+        panic!("{text}");
+    };
+    assert_eq!(token["request"]["method"], "GET");
+    assert_eq!(token["request"]["queryString"][0]["name"], "page");
+    assert!(
+        token["request"]["headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|header| header["value"] == "Bearer [REDACTED]")
+    );
+    assert_eq!(token["response"]["status"], 200);
+    assert_eq!(
+        token["response"]["content"]["text"],
+        "seen Bearer [REDACTED]"
+    );
+    let blob = &blob["response"]["content"];
+    assert_eq!(blob["encoding"], "base64");
+    let blob = base64::engine::general_purpose::STANDARD.decode(blob["text"].as_str().unwrap())?;
+    assert_eq!(blob, b"\xff\xfeBearer [REDACTED]");
+    assert_eq!(moved["request"]["method"], "POST");
+    assert_eq!(moved["request"]["postData"]["text"], "moving");
+    assert_eq!(moved["response"]["status"], 303);
+    assert_eq!(followed["request"]["method"], "GET");
+    assert!(
+        followed["request"]["url"]
+            .as_str()
+            .unwrap()
+            .ends_with("/token")
+    );
+    assert!(followed["request"].get("postData").is_none());
+    assert_eq!(followed["response"]["status"], 200);
+    let started = token["startedDateTime"].as_str().unwrap();
+    assert!(started.len() == 24 && started.ends_with('Z'), "{started}");
+    assert_eq!(refused["pageref"], page("second"));
+    assert_eq!(refused["response"]["status"], 0);
+    assert!(
+        refused["_error"]
+            .as_str()
+            .unwrap()
+            .starts_with("connect failed")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn cli_records_and_replays_a_cassette() -> Result<(), Box<dyn std::error::Error>> {
+    use axum::Router;
+    use axum::routing::get;
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", 0)).await?;
+    let port = listener.local_addr()?.port();
+    let app = Router::new().route("/users/{id}", get(|| async { "Gauss" }));
+    let server = tokio::spawn(async move { axum::serve(listener, app).await });
+
+    let temp = assert_fs::TempDir::new()?;
+    let scenario = |id: u32| {
+        format!(
+            r#"api user {{
+                scheme: http,
+                host: "127.0.0.1",
+                port: {port},
+                headers: {{ Tenant: secret("TENANT") }},
+                get(id: int) {{ method: GET, path: "/users/\(id)?key=\(secret("KEY"))" }}
+            }}
+            test fetch {{ expect user.get({id}).body == "Gauss"; }}"#
+        )
+    };
+    temp.child("scenario.fan").write_str(&scenario(1))?;
+    let cassette = temp.child("users.json");
+    let run = |mode: &'static str| {
+        let mut command = command();
+        command
+            .env("TENANT", "blue-tenant")
+            .env("KEY", "hidden-key")
+            .arg("test")
+            .arg("--path")
+            .arg(temp.path())
+            .arg("--cassette")
+            .arg(cassette.path())
+            .arg(mode)
+            .arg("--match-header")
+            .arg("tenant");
+        command
+    };
+    let recorded = run("--record").output().await?;
+    server.abort();
+    let _ = server.await;
+    assert!(
+        recorded.status.success(),
+        "{}",
+        String::from_utf8_lossy(&recorded.stdout)
+    );
+    let text = std::fs::read_to_string(cassette.path())?;
+    assert!(text.contains("/users/1?key=[REDACTED]"), "{text}");
+    assert!(
+        !text.contains("hidden-key") && !text.contains("blue-tenant"),
+        "{text}"
+    );
+
+    let replayed = run("--replay").output().await?;
+    assert!(
+        replayed.status.success(),
+        "{}",
+        String::from_utf8_lossy(&replayed.stdout)
+    );
+
+    let mut other = run("--replay");
+    let other = other.env("TENANT", "red-tenant").output().await?;
+    assert!(!other.status.success());
+    let stdout = String::from_utf8(other.stdout)?;
+    assert!(stdout.contains("- header tenant: [REDACTED]"), "{stdout}");
+
+    temp.child("scenario.fan").write_str(&scenario(2))?;
+    let unmatched = run("--replay").output().await?;
+    assert!(!unmatched.status.success());
+    let stdout = String::from_utf8(unmatched.stdout)?;
+    assert!(
+        stdout.contains(&format!(
+            "no recorded request matches GET http://127.0.0.1:{port}/users/2"
+        )),
+        "{stdout}"
+    );
+    assert!(
+        stdout.contains(&format!(
+            "- url: http://127.0.0.1:{port}/users/1?key=[REDACTED]\n\
+             + url: http://127.0.0.1:{port}/users/2?key=[REDACTED]"
+        )),
+        "{stdout}"
+    );
+    Ok(())
+}
+
 fn command() -> Command {
     let path = std::env::var_os("CARGO_BIN_EXE_basjoofan")
         .map(PathBuf::from)
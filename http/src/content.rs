@@ -1,8 +1,13 @@
 use tokio::fs::File;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
 use tokio::io::copy;
 
+/// How much of a file is read at a time when a body is sent in pieces, as HTTP/1.1
+/// chunks or HTTP/2 data frames.
+pub const CHUNK: usize = 64 * 1024;
+
 pub enum Content {
     Empty,
     Bytes(Vec<u8>),
@@ -15,6 +20,20 @@ pub enum Part {
 }
 
 impl Content {
+    /// A copy of the content for sending it again, unless it reads from a file.
+    pub fn replay(&self) -> Option<Content> {
+        let parts = match self {
+            Content::Empty => return Some(Content::Empty),
+            Content::Bytes(bytes) => return Some(Content::Bytes(bytes.clone())),
+            Content::Parts(parts) => parts,
+        };
+        let parts = parts.iter().map(|part| match part {
+            Part::Bytes(bytes) => Some(Part::Bytes(bytes.clone())),
+            Part::File(_) => None,
+        });
+        parts.collect::<Option<_>>().map(Content::Parts)
+    }
+
     pub async fn write<W: AsyncWrite + Unpin>(self, mut writer: W) -> Result<(), std::io::Error> {
         match self {
             Content::Empty => Ok(()),
@@ -32,4 +51,44 @@ impl Content {
             }
         }
     }
+
+    /// Writes the content with the chunked transfer coding, one chunk for each
+    /// piece of bytes and for each read from a file, then the last chunk.
+    pub async fn write_chunked<W: AsyncWrite + Unpin>(
+        self,
+        mut writer: W,
+    ) -> Result<(), std::io::Error> {
+        let parts = match self {
+            Content::Empty => Vec::new(),
+            Content::Bytes(bytes) => vec![Part::Bytes(bytes)],
+            Content::Parts(parts) => parts,
+        };
+        for part in parts {
+            match part {
+                Part::Bytes(bytes) => chunk(&mut writer, &bytes).await?,
+                Part::File(mut file) => {
+                    let mut buffer = vec![0; CHUNK];
+                    loop {
+                        let size = file.read(&mut buffer).await?;
+                        if size == 0 {
+                            break;
+                        }
+                        chunk(&mut writer, &buffer[..size]).await?;
+                    }
+                }
+            }
+        }
+        writer.write_all(b"0\r\n\r\n").await
+    }
+}
+
+async fn chunk<W: AsyncWrite + Unpin>(writer: &mut W, data: &[u8]) -> Result<(), std::io::Error> {
+    if data.is_empty() {
+        return Ok(());
+    }
+    writer
+        .write_all(format!("{:x}\r\n", data.len()).as_bytes())
+        .await?;
+    writer.write_all(data).await?;
+    writer.write_all(b"\r\n").await
 }
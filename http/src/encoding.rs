@@ -0,0 +1,299 @@
+use flate2::read::DeflateDecoder;
+use flate2::read::GzDecoder;
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+/// The content codings this transport is able to decode.
+pub const ACCEPT: &str = "gzip, deflate, br";
+
+/// Whether `decode` is able to undo a coding.
+pub fn supports(coding: &str) -> bool {
+    ["gzip", "x-gzip", "deflate", "br", "identity"]
+        .iter()
+        .any(|name| coding.eq_ignore_ascii_case(name))
+}
+
+/// The most bytes a body may be read as or decode to, so that neither a large
+/// declared size nor a small compressed body can exhaust memory.
+pub const LIMIT: u64 = 256 * 1024 * 1024;
+
+/// Decodes a body by undoing each content coding in reverse order of application.
+/// Returns the names of the codings that were removed. On error the body is left
+/// as it was.
+pub fn decode(codings: &str, body: &mut Vec<u8>) -> Result<String, std::io::Error> {
+    decode_within(codings, body, LIMIT)
+}
+
+fn decode_within(codings: &str, body: &mut Vec<u8>, limit: u64) -> Result<String, std::io::Error> {
+    let mut names = Vec::new();
+    let mut decoded: Option<Vec<u8>> = None;
+    for coding in codings.rsplit(',').map(str::trim) {
+        let coding = coding.to_ascii_lowercase();
+        let input = decoded.as_deref().unwrap_or(body);
+        decoded = Some(match coding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => read(GzDecoder::new(input), limit)?,
+            "deflate" => match read(ZlibDecoder::new(input), limit) {
+                Ok(bytes) => bytes,
+                // some servers send raw deflate data without the zlib wrapper
+                Err(_) => read(DeflateDecoder::new(input), limit)?,
+            },
+            "br" => read(brotli::Decompressor::new(input, 4096), limit)?,
+            _ => break,
+        });
+        names.push(coding);
+    }
+    if let Some(decoded) = decoded {
+        *body = decoded;
+    }
+    names.reverse();
+    Ok(names.join(", "))
+}
+
+/// Removes the content codings named by a `Content-Encoding` value from a body.
+/// A body that does not decode is kept as it was received, and the reason is
+/// returned along with the removed codings.
+pub fn decode_content(codings: Option<&str>, body: &mut Vec<u8>) -> (String, Option<String>) {
+    match codings {
+        Some(codings) if !body.is_empty() => match decode(codings, body) {
+            Ok(names) => (names, None),
+            Err(error) => (String::default(), Some(error.to_string())),
+        },
+        _ => (String::default(), None),
+    }
+}
+
+/// Removes content codings from a body piece by piece as it arrives, so that an
+/// event stream can be parsed before the body ends.
+pub struct Decoder {
+    stages: Vec<(String, Stage)>,
+    decoded: u64,
+}
+
+enum Stage {
+    Gzip(flate2::write::GzDecoder<Vec<u8>>),
+    /// Deflate with the zlib wrapper, until the first bytes show it is missing
+    Zlib(flate2::write::ZlibDecoder<Vec<u8>>, bool),
+    Deflate(flate2::write::DeflateDecoder<Vec<u8>>),
+    Br(Box<brotli::DecompressorWriter<Vec<u8>>>),
+}
+
+impl Decoder {
+    /// Builds a decoder for a `Content-Encoding` value, or `None` when one of its
+    /// codings is not supported.
+    pub fn new(codings: &str) -> Option<Self> {
+        let mut stages = Vec::new();
+        for coding in codings.rsplit(',').map(str::trim) {
+            let coding = coding.to_ascii_lowercase();
+            let stage = match coding.as_str() {
+                "" | "identity" => continue,
+                "gzip" | "x-gzip" => Stage::Gzip(flate2::write::GzDecoder::new(Vec::new())),
+                "deflate" => Stage::Zlib(flate2::write::ZlibDecoder::new(Vec::new()), false),
+                "br" => Stage::Br(Box::new(brotli::DecompressorWriter::new(Vec::new(), 4096))),
+                _ => return None,
+            };
+            stages.push((coding, stage));
+        }
+        Some(Self { stages, decoded: 0 })
+    }
+
+    /// The names of the codings being removed, in the order they were applied.
+    pub fn names(&self) -> String {
+        let names: Vec<&str> = self
+            .stages
+            .iter()
+            .rev()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        names.join(", ")
+    }
+
+    /// Decodes the next piece of the body, returning the bytes it decoded to.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+        self.run(data.to_vec(), false)
+    }
+
+    /// Ends the body, returning the last decoded bytes. Fails when the body stopped
+    /// before its codings did.
+    pub fn finish(&mut self) -> Result<Vec<u8>, std::io::Error> {
+        self.run(Vec::new(), true)
+    }
+
+    fn run(&mut self, mut data: Vec<u8>, last: bool) -> Result<Vec<u8>, std::io::Error> {
+        for (_, stage) in &mut self.stages {
+            data = stage.write(&data, last)?;
+        }
+        self.decoded += data.len() as u64;
+        if self.decoded > LIMIT {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("decoded body exceeds {LIMIT} bytes"),
+            ));
+        }
+        Ok(data)
+    }
+}
+
+impl Stage {
+    fn write(&mut self, data: &[u8], last: bool) -> Result<Vec<u8>, std::io::Error> {
+        use std::io::Write;
+        if let Stage::Zlib(decoder, started) = self {
+            match decoder.write_all(data) {
+                Ok(()) => *started |= !data.is_empty(),
+                // some servers send raw deflate data without the zlib wrapper
+                Err(_) if !*started => {
+                    *self = Stage::Deflate(flate2::write::DeflateDecoder::new(Vec::new()));
+                    return self.write(data, last);
+                }
+                Err(error) => return Err(error),
+            }
+        }
+        match self {
+            Stage::Gzip(decoder) => {
+                decoder.write_all(data)?;
+                match last {
+                    true => decoder.try_finish()?,
+                    false => decoder.flush()?,
+                }
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Stage::Zlib(decoder, _) => {
+                match last {
+                    true => decoder.try_finish()?,
+                    false => decoder.flush()?,
+                }
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Stage::Deflate(decoder) => {
+                decoder.write_all(data)?;
+                match last {
+                    true => decoder.try_finish()?,
+                    false => decoder.flush()?,
+                }
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+            Stage::Br(decoder) => {
+                decoder.write_all(data)?;
+                match last {
+                    true => decoder.close()?,
+                    false => decoder.flush()?,
+                }
+                Ok(std::mem::take(decoder.get_mut()))
+            }
+        }
+    }
+}
+
+fn read<R: Read>(reader: R, limit: u64) -> Result<Vec<u8>, std::io::Error> {
+    let mut bytes = Vec::new();
+    reader.take(limit + 1).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 > limit {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("decoded body exceeds {limit} bytes"),
+        ));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Decoder;
+    use super::decode;
+    use super::decode_content;
+    use super::decode_within;
+    use std::io::Write;
+
+    #[test]
+    fn test_decode_each_coding() {
+        let text = b"hello, hello, hello, compressed world";
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(text).unwrap();
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(text).unwrap();
+        let mut deflate =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        deflate.write_all(text).unwrap();
+        let mut br = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+        br.write_all(text).unwrap();
+        for (coding, bytes) in [
+            ("gzip", gzip.finish().unwrap()),
+            ("deflate", zlib.finish().unwrap()),
+            ("deflate", deflate.finish().unwrap()),
+            ("br", br.into_inner()),
+        ] {
+            let mut body = bytes;
+            assert_eq!(coding, decode(coding, &mut body).unwrap());
+            assert_eq!(&text[..], &body[..]);
+        }
+    }
+
+    #[test]
+    fn test_decode_stacked_and_unknown_codings() {
+        let mut inner = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        inner.write_all(b"twice").unwrap();
+        let mut outer = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        outer.write_all(&inner.finish().unwrap()).unwrap();
+        let mut body = outer.finish().unwrap();
+        assert_eq!("gzip, deflate", decode("gzip, deflate", &mut body).unwrap());
+        assert_eq!(b"twice", &body[..]);
+
+        let mut body = b"unchanged".to_vec();
+        assert_eq!("", decode("zstd", &mut body).unwrap());
+        assert_eq!(b"unchanged", &body[..]);
+
+        let mut body = b"not gzip".to_vec();
+        assert!(decode("gzip", &mut body).is_err());
+        assert_eq!(b"not gzip", &body[..]);
+    }
+
+    #[test]
+    fn test_decoder_decodes_pieces_as_they_arrive() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"data: one\n\n").unwrap();
+        gzip.flush().unwrap();
+        let first = gzip.get_ref().len();
+        gzip.write_all(b"data: two\n\n").unwrap();
+        let body = gzip.finish().unwrap();
+
+        let mut decoder = Decoder::new("gzip").unwrap();
+        assert_eq!("gzip", decoder.names());
+        assert_eq!(b"data: one\n\n", &decoder.feed(&body[..first]).unwrap()[..]);
+        let mut rest = decoder.feed(&body[first..]).unwrap();
+        rest.extend(decoder.finish().unwrap());
+        assert_eq!(b"data: two\n\n", &rest[..]);
+
+        let mut decoder = Decoder::new("gzip").unwrap();
+        decoder.feed(&body[..first]).unwrap();
+        assert!(decoder.finish().is_err());
+        assert!(Decoder::new("zstd").is_none());
+    }
+
+    #[test]
+    fn test_decode_content_keeps_corrupt_bodies() {
+        let mut body = b"not gzip".to_vec();
+        let (names, error) = decode_content(Some("gzip"), &mut body);
+        assert_eq!("", names);
+        assert!(error.is_some());
+        assert_eq!(b"not gzip", &body[..]);
+
+        let mut body = Vec::new();
+        assert_eq!(
+            (String::new(), None),
+            decode_content(Some("gzip"), &mut body)
+        );
+    }
+
+    #[test]
+    fn test_decode_stops_at_the_limit() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&[0; 64 * 1024]).unwrap();
+        let bomb = gzip.finish().unwrap();
+        let mut body = bomb.clone();
+        let error = decode_within("gzip", &mut body, 1024).unwrap_err();
+        assert_eq!("decoded body exceeds 1024 bytes", error.to_string());
+        assert_eq!(bomb, body);
+        assert_eq!("gzip", decode_within("gzip", &mut body, 64 * 1024).unwrap());
+        assert_eq!(64 * 1024, body.len());
+    }
+}
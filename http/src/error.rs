@@ -5,7 +5,22 @@ pub enum Error {
     ConnectFailed(std::io::Error),
     ConnectTimeout,
     NoConnectionAvailable,
-    TlsHandshakeFailed,
+    TlsHandshakeFailed(std::io::Error),
+    TlsConfig(String),
+    ReadTimeout,
+    TotalTimeout,
+    Io(std::io::Error),
+    Http2(h2::Error),
+    Undecodable(String),
+    TooManyRedirects(usize),
+    Downgrade(String),
+    InvalidProxy(String),
+    TunnelFailed(String),
+    InvalidResolve(String),
+    InvalidSocket(String),
+    WebSocket(tokio_tungstenite::tungstenite::Error),
+    WebSocketHandshake(String),
+    WebSocketClosed,
 }
 
 impl std::error::Error for Error {}
@@ -18,7 +33,59 @@ impl std::fmt::Display for Error {
             Error::ConnectFailed(error) => write!(f, "connect failed: {error}"),
             Error::ConnectTimeout => write!(f, "connect timeout"),
             Error::NoConnectionAvailable => write!(f, "no connection available"),
-            Error::TlsHandshakeFailed => write!(f, "tls handshake failed"),
+            Error::TlsHandshakeFailed(error) => write!(f, "tls handshake failed: {error}"),
+            Error::TlsConfig(reason) => write!(f, "invalid tls config: {reason}"),
+            Error::ReadTimeout => write!(f, "read timeout"),
+            Error::TotalTimeout => write!(f, "total timeout"),
+            Error::Io(error) => write!(f, "{error}"),
+            Error::Http2(error) => write!(f, "http2 error: {error}"),
+            Error::Undecodable(reason) => write!(f, "undecodable body: {reason}"),
+            Error::TooManyRedirects(hops) => write!(f, "too many redirects: gave up after {hops}"),
+            Error::Downgrade(url) => write!(f, "refused redirect from https to {url}"),
+            Error::InvalidProxy(proxy) => write!(f, "invalid proxy: {proxy}"),
+            Error::TunnelFailed(status) => write!(f, "proxy tunnel failed: {status}"),
+            Error::InvalidResolve(entry) => write!(f, "invalid resolve address: {entry}"),
+            Error::InvalidSocket(reason) => write!(f, "invalid socket: {reason}"),
+            Error::WebSocket(error) => write!(f, "websocket error: {error}"),
+            Error::WebSocketHandshake(reason) => write!(f, "websocket handshake failed: {reason}"),
+            Error::WebSocketClosed => write!(f, "websocket closed"),
         }
     }
 }
+
+impl From<Error> for lib::Error {
+    fn from(error: Error) -> Self {
+        use lib::ErrorKind;
+        use std::io::ErrorKind as Io;
+        let kind = match &error {
+            Error::InvalidUrlHost | Error::InvalidResolve(_) | Error::InvalidSocket(_) => {
+                ErrorKind::Invalid
+            }
+            Error::HostNotFound(_) => ErrorKind::Dns,
+            Error::ConnectFailed(error) if error.kind() == Io::ConnectionRefused => {
+                ErrorKind::Refused
+            }
+            Error::ConnectFailed(_) | Error::NoConnectionAvailable => ErrorKind::Connect,
+            Error::ConnectTimeout => ErrorKind::ConnectTimeout,
+            Error::TlsHandshakeFailed(_) | Error::TlsConfig(_) => ErrorKind::Tls,
+            Error::ReadTimeout => ErrorKind::ReadTimeout,
+            Error::TotalTimeout => ErrorKind::Timeout,
+            Error::Io(error) => match error.kind() {
+                Io::InvalidInput => ErrorKind::Invalid,
+                Io::ConnectionReset
+                | Io::ConnectionAborted
+                | Io::BrokenPipe
+                | Io::UnexpectedEof => ErrorKind::Closed,
+                _ => ErrorKind::Io,
+            },
+            Error::Http2(_)
+            | Error::Undecodable(_)
+            | Error::WebSocket(_)
+            | Error::WebSocketHandshake(_) => ErrorKind::Protocol,
+            Error::TooManyRedirects(_) | Error::Downgrade(_) => ErrorKind::Redirect,
+            Error::InvalidProxy(_) | Error::TunnelFailed(_) => ErrorKind::Proxy,
+            Error::WebSocketClosed => ErrorKind::Closed,
+        };
+        lib::Error::new(kind, error.to_string())
+    }
+}
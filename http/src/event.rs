@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+/// One event dispatched from a `text/event-stream` body.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    /// The last event id seen in the stream, empty until one is sent
+    pub id: String,
+    /// The event type, `message` unless the event names another
+    pub event: String,
+    /// The data lines joined with newlines
+    pub data: String,
+    /// The reconnection time in milliseconds last sent by the server
+    pub retry: Option<u64>,
+}
+
+/// When to stop reading an event stream before the server ends it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Events {
+    /// Stop once this many events have arrived
+    pub count: Option<usize>,
+    /// Stop after the first event of this type
+    pub until: Option<String>,
+    /// Stop when this much time has passed since the body started
+    pub tiomeout: Option<Duration>,
+}
+
+impl Events {
+    /// The number of events to keep, or `None` while the stream should be read on.
+    pub fn end(&self, events: &[Event]) -> Option<usize> {
+        let until = self.until.as_ref().and_then(|until| {
+            events
+                .iter()
+                .position(|event| &event.event == until)
+                .map(|index| index + 1)
+        });
+        let count = self.count.filter(|count| events.len() >= *count);
+        until.into_iter().chain(count).min()
+    }
+}
+
+/// Splits an event stream into events as its bytes arrive, following the parsing
+/// rules of the HTML event stream format.
+#[derive(Debug, Default)]
+pub struct Parser {
+    /// Bytes of a line that has not ended yet
+    line: Vec<u8>,
+    /// Whether the last byte was a carriage return, so a following line feed
+    /// belongs to the same line ending
+    after_return: bool,
+    started: bool,
+    id: String,
+    event: String,
+    data: Vec<String>,
+    retry: Option<u64>,
+    pub events: Vec<Event>,
+}
+
+impl Parser {
+    pub fn feed(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            match byte {
+                b'\n' if self.after_return => self.after_return = false,
+                b'\r' | b'\n' => {
+                    self.after_return = byte == b'\r';
+                    let line = std::mem::take(&mut self.line);
+                    self.line(&line);
+                }
+                byte => {
+                    self.after_return = false;
+                    self.line.push(byte);
+                }
+            }
+        }
+    }
+
+    fn line(&mut self, line: &[u8]) {
+        let mut line = String::from_utf8_lossy(line).into_owned();
+        if !self.started {
+            self.started = true;
+            if let Some(rest) = line.strip_prefix('\u{feff}') {
+                line = rest.to_string();
+            }
+        }
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        let (field, value) = line.split_once(':').unwrap_or((&line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "event" => self.event = value.to_string(),
+            "data" => self.data.push(value.to_string()),
+            "id" if !value.contains('\0') => self.id = value.to_string(),
+            "retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                self.retry = value.parse().ok();
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self) {
+        let event = std::mem::take(&mut self.event);
+        if self.data.is_empty() {
+            return;
+        }
+        self.events.push(Event {
+            id: self.id.clone(),
+            event: if event.is_empty() {
+                String::from("message")
+            } else {
+                event
+            },
+            data: std::mem::take(&mut self.data).join("\n"),
+            retry: self.retry,
+        });
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Event;
+    use super::Events;
+    use super::Parser;
+
+    #[test]
+    fn test_parse_events_across_pieces() {
+        let mut parser = Parser::default();
+        for piece in [
+            "\u{feff}: comment\r\nretry: 1500\r\nid: 1\r\ndata: first\r",
+            "\ndata:second\r\n\r\nevent: update\nid: 2\ndata: {\"n\":",
+            "2}\n\nid\ndata\n\nevent: ignored\n\ndata: unfinished",
+        ] {
+            parser.feed(piece.as_bytes());
+        }
+        let event = |id: &str, event: &str, data: &str| Event {
+            id: id.into(),
+            event: event.into(),
+            data: data.into(),
+            retry: Some(1500),
+        };
+        assert_eq!(
+            parser.events,
+            [
+                event("1", "message", "first\nsecond"),
+                event("2", "update", r#"{"n":2}"#),
+                event("", "message", ""),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_end() {
+        let events: Vec<_> = ["message", "update", "done", "message"]
+            .into_iter()
+            .map(|name| Event {
+                event: name.into(),
+                ..Default::default()
+            })
+            .collect();
+        let until = |count: Option<usize>, until: Option<&str>| Events {
+            count,
+            until: until.map(String::from),
+            tiomeout: None,
+        };
+        assert_eq!(until(None, None).end(&events), None);
+        assert_eq!(until(Some(2), None).end(&events), Some(2));
+        assert_eq!(until(Some(9), None).end(&events), None);
+        assert_eq!(until(None, Some("done")).end(&events), Some(3));
+        assert_eq!(until(Some(1), Some("done")).end(&events), Some(1));
+        assert_eq!(until(None, Some("missing")).end(&events), None);
+    }
+}
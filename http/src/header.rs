@@ -31,6 +31,14 @@ impl Headers {
         }
     }
 
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.indices
+            .get(name.to_lowercase().as_str())
+            .and_then(|indices| indices.first())
+            .and_then(|index| self.entries.get(*index))
+            .map(|header| header.value.as_str())
+    }
+
     pub fn iter(&self) -> Iter<'_, Header> {
         self.entries.iter()
     }
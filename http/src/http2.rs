@@ -0,0 +1,217 @@
+use super::Content;
+use super::Error;
+use super::Events;
+use super::Headers;
+use super::Part;
+use super::Request;
+use super::Response;
+use super::Stream;
+use super::Version;
+use super::content;
+use super::encoding;
+use super::event;
+use super::response::is_event_stream;
+use bytes::Bytes;
+use h2::RecvStream;
+use h2::SendStream;
+use h2::client::ResponseFuture;
+use h2::client::SendRequest;
+use std::future::poll_fn;
+use tokio::io::AsyncReadExt;
+
+/// A handle for opening streams on one HTTP/2 connection.
+pub type Session = SendRequest<Bytes>;
+
+/// Header fields that are specific to an HTTP/1.1 connection and are not allowed in
+/// HTTP/2; the authority travels in the `:authority` pseudo-header instead of `Host`.
+const CONNECTION_HEADERS: [&str; 6] = [
+    "host",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Sends the connection preface on a new stream and drives the connection in the
+/// background for as long as any session handle is alive.
+pub async fn handshake(stream: Stream) -> Result<Session, Error> {
+    let (session, connection) = h2::client::handshake(stream).await.map_err(Error::Http2)?;
+    tokio::spawn(async move {
+        let _ = connection.await;
+    });
+    Ok(session)
+}
+
+/// Opens a stream for the request and writes its headers and body, returning the
+/// future of the response head.
+pub async fn send(
+    session: Session,
+    request: &Request,
+    content: Content,
+) -> Result<ResponseFuture, Error> {
+    let uri = request.url.to_string();
+    let uri = uri.split('#').next().unwrap_or_default();
+    let mut builder = hyperium_http::Request::builder()
+        .method(request.method.as_ref())
+        .uri(uri)
+        .version(hyperium_http::Version::HTTP_2);
+    for header in request.headers.iter() {
+        let name = header.name.to_ascii_lowercase();
+        if CONNECTION_HEADERS.contains(&name.as_str())
+            || name == "te" && !header.value.eq_ignore_ascii_case("trailers")
+        {
+            continue;
+        }
+        builder = builder.header(name, header.value.as_str());
+    }
+    let head = builder
+        .body(())
+        .map_err(|error| Error::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, error)))?;
+    let mut session = session.ready().await.map_err(Error::Http2)?;
+    let empty = matches!(content, Content::Empty);
+    let (response, mut stream) = session.send_request(head, empty).map_err(Error::Http2)?;
+    if !empty {
+        let parts = match content {
+            Content::Empty => Vec::new(),
+            Content::Bytes(bytes) => vec![Part::Bytes(bytes)],
+            Content::Parts(parts) => parts,
+        };
+        for part in parts {
+            match part {
+                Part::Bytes(bytes) => write(&mut stream, Bytes::from(bytes)).await?,
+                Part::File(mut file) => {
+                    let mut buffer = vec![0; content::CHUNK];
+                    loop {
+                        let size = file.read(&mut buffer).await.map_err(Error::Io)?;
+                        if size == 0 {
+                            break;
+                        }
+                        write(&mut stream, Bytes::copy_from_slice(&buffer[..size])).await?;
+                    }
+                }
+            }
+        }
+        stream.send_data(Bytes::new(), true).map_err(Error::Http2)?;
+    }
+    Ok(response)
+}
+
+/// Writes data in pieces no larger than the peer's flow-control window allows.
+async fn write(stream: &mut SendStream<Bytes>, mut data: Bytes) -> Result<(), Error> {
+    while !data.is_empty() {
+        stream.reserve_capacity(data.len());
+        let capacity = poll_fn(|context| stream.poll_capacity(context))
+            .await
+            .ok_or_else(|| Error::Io(std::io::ErrorKind::BrokenPipe.into()))?
+            .map_err(Error::Http2)?;
+        let chunk = data.split_to(capacity.min(data.len()));
+        stream.send_data(chunk, false).map_err(Error::Http2)?;
+    }
+    Ok(())
+}
+
+/// Reads the body and trailers that follow a response head. An event stream body is
+/// decoded and parsed as it arrives, and only read until `events` says to stop.
+pub async fn receive(
+    head: hyperium_http::Response<RecvStream>,
+    events: &Events,
+) -> Result<Response, Error> {
+    let (parts, mut stream) = head.into_parts();
+    let headers = fields(&parts.headers);
+    let mut body = Vec::new();
+    let mut parser = event::Parser::default();
+    let mut decoder = match is_event_stream(&headers) {
+        true => encoding::Decoder::new(headers.get("content-encoding").unwrap_or_default()),
+        false => None,
+    };
+    let incremental = decoder.is_some();
+    let mut decoded = Vec::new();
+    let read = async {
+        while let Some(data) = stream.data().await {
+            let data = data.map_err(Error::Http2)?;
+            stream
+                .flow_control()
+                .release_capacity(data.len())
+                .map_err(Error::Http2)?;
+            body.extend_from_slice(&data);
+            if let Some(stage) = decoder.as_mut() {
+                match stage.feed(&data) {
+                    Ok(data) => {
+                        parser.feed(&data);
+                        decoded.extend(data);
+                    }
+                    // left for decode_content to report once the body is read
+                    Err(_) => decoder = None,
+                }
+                if events.end(&parser.events).is_some() {
+                    return Ok(false);
+                }
+            }
+        }
+        Ok(true)
+    };
+    let complete = match events.tiomeout.filter(|_| incremental) {
+        Some(tiomeout) => tokio::time::timeout(tiomeout, read)
+            .await
+            .unwrap_or(Ok(false))?,
+        None => read.await?,
+    };
+    let trailers = match complete {
+        true => stream.trailers().await.map_err(Error::Http2)?,
+        false => None,
+    };
+    let transferred = body.len();
+    // a stream cut short at the last wanted event keeps what decoded so far
+    let finished = match decoder.as_mut() {
+        Some(decoder) if complete => decoder
+            .finish()
+            .map(|data| {
+                parser.feed(&data);
+                decoded.extend(data);
+            })
+            .is_ok(),
+        Some(_) => true,
+        None => false,
+    };
+    let (encoding, undecoded) = match decoder {
+        Some(decoder) if finished => {
+            body = decoded;
+            (decoder.names(), None)
+        }
+        _ => encoding::decode_content(headers.get("content-encoding"), &mut body),
+    };
+    if let Some(end) = events.end(&parser.events) {
+        parser.events.truncate(end);
+    }
+    Ok(Response {
+        version: Version::Http20.to_string(),
+        status: parts.status.as_u16(),
+        reason: parts
+            .status
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string(),
+        headers,
+        body,
+        trailers: trailers.as_ref().map(fields).unwrap_or_default(),
+        encoding,
+        undecoded,
+        transferred,
+        keep_alive: true,
+        reused: false,
+        tls: None,
+        events: parser.events,
+    })
+}
+
+fn fields(map: &hyperium_http::HeaderMap) -> Headers {
+    let mut headers = Headers::default();
+    for (name, value) in map {
+        headers.insert(
+            name.to_string(),
+            String::from_utf8_lossy(value.as_bytes()).into_owned(),
+        );
+    }
+    headers
+}
@@ -1,35 +1,132 @@
 mod client;
 mod content;
+mod encoding;
 mod error;
+mod event;
 mod header;
+mod http2;
 mod mime;
+mod pool;
+mod proxy;
 mod request;
 mod response;
 mod stream;
 mod time;
+mod tls;
 mod url;
+mod websocket;
 
 use content::Content;
 use content::Part;
 use error::Error;
+pub use event::Event;
+pub use event::Events;
 pub use header::Headers;
+use pool::Pool;
+pub use proxy::Proxy;
+use stream::Dialer;
 use stream::Stream;
 pub use time::Time;
+pub use tls::Certificate;
+pub use tls::Tls;
+pub use tls::TlsSession;
 pub use url::Serializer;
 pub use url::Url;
+pub use websocket::WebSocket;
 
-#[derive(Default)]
+/// An HTTP client whose clones share one connection pool.
+#[derive(Clone)]
 pub struct Client {
     connect_tiomeout: std::time::Duration,
-    // TODO read_tiomeout: Option<std::time::Duration>,
+    read_tiomeout: Option<std::time::Duration>,
+    total_tiomeout: Option<std::time::Duration>,
+    dialer: Dialer,
+    events: Events,
+    pool: Pool,
 }
 
 impl Client {
     pub fn new() -> Self {
         Self {
             connect_tiomeout: std::time::Duration::from_secs(120),
+            read_tiomeout: None,
+            total_tiomeout: None,
+            dialer: Dialer::default(),
+            events: Events::default(),
+            pool: Pool::default(),
         }
     }
+
+    /// Sets the connect deadline, the deadline for the first response byte after the
+    /// request is written, and the deadline for the whole exchange.
+    pub fn with_timeouts(
+        mut self,
+        connect: std::time::Duration,
+        read: Option<std::time::Duration>,
+        total: Option<std::time::Duration>,
+    ) -> Self {
+        self.connect_tiomeout = connect;
+        self.read_tiomeout = read;
+        self.total_tiomeout = total;
+        self
+    }
+
+    /// Sets the HTTP version to speak. HTTP/2.0 uses prior knowledge over plain
+    /// connections; without a version, TLS connections negotiate h2 or HTTP/1.1.
+    pub fn with_version(mut self, version: Option<Version>) -> Self {
+        self.dialer.version = version;
+        self
+    }
+
+    /// Sets the proxy that connections go through.
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        self.dialer.proxy = proxy;
+        self
+    }
+
+    /// Sets the trusted CAs, client certificate and verification mode for TLS. The
+    /// PEM files are read when the first TLS connection with these settings is made,
+    /// and kept for the connections after it.
+    pub fn with_tls(mut self, tls: Tls) -> Self {
+        self.dialer.tls = tls;
+        self
+    }
+
+    /// Sets the addresses to connect to instead of resolving `host:port` pairs, in
+    /// the manner of curl's `--resolve`. The url's host is still sent for SNI and in
+    /// the `Host` header.
+    pub fn with_resolve(
+        mut self,
+        resolve: std::collections::HashMap<String, Vec<std::net::IpAddr>>,
+    ) -> Self {
+        self.dialer.resolve = resolve;
+        self
+    }
+
+    /// Sets a Unix domain socket to connect to instead of the url's host and port.
+    pub fn with_socket(mut self, socket: Option<String>) -> Self {
+        self.dialer.socket = socket;
+        self
+    }
+
+    /// Sets when to stop reading an event stream response.
+    pub fn with_events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Sets how long an idle connection is kept for reuse and how many connections
+    /// may be open to one host at a time.
+    pub fn with_pool(mut self, idle_timeout: std::time::Duration, max_per_host: usize) -> Self {
+        self.pool = Pool::new(idle_timeout, max_per_host);
+        self
+    }
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 #[derive(Default, Debug)]
@@ -57,114 +154,568 @@ pub struct Response {
     /// The response's headers
     pub headers: Headers,
     /// The response's body
-    pub body: String,
+    pub body: Vec<u8>,
+    /// The response's trailers
+    pub trailers: Headers,
+    /// The content codings removed from the body
+    pub encoding: String,
+    /// Why the content codings could not be removed, leaving the body as received
+    pub undecoded: Option<String>,
+    /// The body length as received, before decoding
+    pub transferred: usize,
+    /// Whether the connection may carry another request
+    pub keep_alive: bool,
+    /// Whether the response arrived on a reused connection
+    pub reused: bool,
+    /// The TLS session the response arrived over, if any
+    pub tls: Option<TlsSession>,
+    /// The events parsed from a `text/event-stream` body
+    pub events: Vec<Event>,
 }
 
+/// Sends a script request on a client of its own, so no connection is reused.
+#[deprecated(note = "use `Client::send`, which reuses connections")]
 pub async fn send(request: lib::Request) -> lib::Result {
-    let mut message = format!("{} {}\n", request.method, request.url);
-    for header in &request.headers {
-        message.push_str(&format!("{}: {}\n", header.name, header.value));
-    }
-    if let Some(lib::Content::Text(body)) = &request.body {
-        message.push('\n');
-        message.push_str(body);
-    }
-    let (mut parsed, mut content) = match Request::from(&message).await {
-        Ok(result) => result,
-        Err(error) => {
-            return lib::Result {
-                request,
-                error: error.to_string(),
-                ..Default::default()
-            };
-        }
-    };
-    if let Some(lib::Content::File(path)) = &request.body {
-        match tokio::fs::File::open(path).await {
-            Ok(file) => match file.metadata().await {
-                Ok(metadata) => {
-                    parsed
-                        .headers
-                        .replace("content-length", metadata.len().to_string());
-                    parsed.body = format!("@{path}");
-                    content = Content::Parts(vec![Part::File(file)]);
+    Client::new().send(request).await
+}
+
+impl Client {
+    /// Sends a script request and converts the outcome into a script result,
+    /// following up to `request.redirects` redirects. The responses that redirected
+    /// are kept in order on the final response. A redirect past the last hop, or
+    /// from https to http without `request.downgrade`, is returned with an error.
+    pub async fn send(&self, request: lib::Request) -> lib::Result {
+        let mut result = self.send_once(request.clone()).await;
+        let mut current = request;
+        let mut redirects = Vec::new();
+        while result.error.is_none() && current.redirects > 0 {
+            let next = match redirect(&current, &result.response) {
+                Ok(Some(_)) if redirects.len() == current.redirects => {
+                    result.error = Some(Error::TooManyRedirects(current.redirects).into());
+                    break;
                 }
+                Ok(Some(next)) => next,
+                Ok(None) => break,
                 Err(error) => {
-                    return lib::Result {
-                        request,
-                        error: error.to_string(),
-                        ..Default::default()
-                    };
+                    result.error = Some(error.into());
+                    break;
                 }
-            },
+            };
+            let followed = self.send_once(next.clone()).await;
+            result.followed.push(followed.request);
+            redirects.push(std::mem::replace(&mut result.response, followed.response));
+            result.timing = lib::Timing {
+                start: result.timing.start,
+                total: followed.timing.end.saturating_sub(result.timing.start),
+                ..followed.timing
+            };
+            result.error = followed.error;
+            current = next;
+        }
+        result.response.redirects = redirects;
+        result
+    }
+
+    /// Opens a WebSocket with the script request as its opening handshake. The
+    /// socket is returned when the server switched protocols; any other response is
+    /// recorded as it is.
+    pub async fn open(&self, mut request: lib::Request) -> (lib::Result, Option<WebSocket>) {
+        let (client, parsed, _) = match self.prepare(&mut request).await {
+            Ok(prepared) => prepared,
+            Err(error) => {
+                let result = lib::Result {
+                    request,
+                    error: Some(error.into()),
+                    ..Default::default()
+                };
+                return (result, None);
+            }
+        };
+        let (response, timing, error, socket) = client.open_request(parsed).await;
+        let result = lib::Result {
+            response: response_of(&request, response),
+            timing: timing_of(timing),
+            request,
+            error: error.map(lib::Error::from),
+            ..Default::default()
+        };
+        (result, socket)
+    }
+
+    async fn send_once(&self, mut request: lib::Request) -> lib::Result {
+        if !request
+            .headers
+            .iter()
+            .any(|header| header.name.eq_ignore_ascii_case("accept-encoding"))
+        {
+            request.headers.push(lib::Header {
+                name: String::from("Accept-Encoding"),
+                value: String::from(encoding::ACCEPT),
+            });
+        }
+        let (client, parsed, content) = match self.prepare(&mut request).await {
+            Ok(prepared) => prepared,
             Err(error) => {
                 return lib::Result {
                     request,
-                    error: error.to_string(),
+                    error: Some(error.into()),
                     ..Default::default()
                 };
             }
+        };
+        let (_, response, timing, error) = client.send_request(parsed, content).await;
+        lib::Result {
+            response: response_of(&request, response),
+            timing: timing_of(timing),
+            request,
+            error: error.map(lib::Error::from),
+            ..Default::default()
         }
     }
-    let (sent, response, timing, error) = Client::new().send_request(parsed, content).await;
-    let _ = sent;
-    lib::Result {
-        request,
-        response: lib::Response {
-            version: response.version,
-            status: response.status,
-            reason: response.reason,
-            headers: response
+
+    /// Parses a script request and derives the client that sends it, with the
+    /// request's timeouts, version, proxy, TLS, resolve, socket and event settings.
+    async fn prepare(
+        &self,
+        request: &mut lib::Request,
+    ) -> Result<(Client, Request, Content), Error> {
+        let mut message = format!("{} {}\n", request.method, request.url);
+        for header in &request.headers {
+            message.push_str(&format!("{}: {}\n", header.name, header.value));
+        }
+        if let Some(lib::Content::Text(body)) = &request.body {
+            message.push('\n');
+            message.push_str(body);
+        }
+        let (mut parsed, mut content) = Request::from(&message).await.map_err(Error::Io)?;
+        if let Some(lib::Content::File(path)) = &request.body {
+            let file = tokio::fs::File::open(path).await.map_err(Error::Io)?;
+            let metadata = file.metadata().await.map_err(Error::Io)?;
+            parsed
                 .headers
-                .iter()
-                .map(|header| lib::Header {
-                    name: header.name.clone(),
-                    value: header.value.clone(),
+                .replace("content-length", metadata.len().to_string());
+            parsed.body = format!("@{path}");
+            content = Content::Parts(vec![Part::File(file)]);
+        }
+        if let Some(lib::Content::Parts(parts)) = &request.body {
+            content = parsed.multipart(parts).await.map_err(Error::Io)?;
+        }
+        let version = match request.version.as_str() {
+            "" => self.dialer.version,
+            version => Some(Version::from(version)),
+        };
+        let proxy = match request.proxy.as_deref() {
+            Some("") => None,
+            Some(proxy) => Some(Proxy::parse(proxy)?),
+            None => self
+                .dialer
+                .proxy
+                .clone()
+                .or_else(|| Proxy::from_env(&parsed.url)),
+        };
+        let proxy = proxy.filter(|_| request.socket.is_none());
+        let mut resolve = std::collections::HashMap::<_, Vec<_>>::new();
+        for (host, address) in &request.resolve {
+            let address = address
+                .trim_matches(['[', ']'])
+                .parse()
+                .map_err(|_| Error::InvalidResolve(format!("{host} {address}")))?;
+            resolve
+                .entry(host.to_ascii_lowercase())
+                .or_default()
+                .push(address);
+        }
+        let client = self
+            .clone()
+            .with_timeouts(
+                request.timeout.connect.unwrap_or(self.connect_tiomeout),
+                request.timeout.read.or(self.read_tiomeout),
+                request.timeout.total.or(self.total_tiomeout),
+            )
+            .with_version(version)
+            .with_proxy(proxy)
+            .with_tls(Tls {
+                ca: request.tls.ca.clone(),
+                cert: request.tls.cert.clone(),
+                key: request.tls.key.clone(),
+                insecure: request.tls.insecure,
+            })
+            .with_resolve(resolve)
+            .with_socket(request.socket.clone())
+            .with_events(Events {
+                count: request.events.count,
+                until: request.events.until.clone(),
+                tiomeout: request.events.timeout,
+            });
+        Ok((client, parsed, content))
+    }
+}
+
+fn response_of(request: &lib::Request, response: Response) -> lib::Response {
+    lib::Response {
+        url: request.url.clone(),
+        version: response.version,
+        status: response.status,
+        reason: response.reason,
+        headers: headers(&response.headers),
+        body: response.body,
+        trailers: headers(&response.trailers),
+        encoding: response.encoding,
+        transferred: response.transferred,
+        reused: response.reused,
+        tls: response.tls.map(|session| lib::TlsSession {
+            version: session.version,
+            cipher: session.cipher,
+            alpn: session.alpn,
+            certificates: session
+                .certificates
+                .into_iter()
+                .map(|certificate| lib::Certificate {
+                    subject: certificate.subject,
+                    issuer: certificate.issuer,
+                    names: certificate.names,
+                    not_before: certificate.not_before,
+                    not_after: certificate.not_after,
                 })
                 .collect(),
-            body: response.body,
-        },
-        timing: lib::Timing {
-            start: timing.start,
-            end: timing.end,
-            total: timing.total,
-            resolve: timing.resolve,
-            connect: timing.connect,
-            write: timing.write,
-            delay: timing.delay,
-            read: timing.read,
-        },
-        error,
+        }),
+        redirects: Vec::new(),
+        events: response
+            .events
+            .into_iter()
+            .map(|event| lib::Event {
+                id: event.id,
+                event: event.event,
+                data: event.data,
+                retry: event.retry,
+            })
+            .collect(),
     }
 }
 
+fn timing_of(timing: Time) -> lib::Timing {
+    lib::Timing {
+        start: timing.start,
+        end: timing.end,
+        total: timing.total,
+        resolve: timing.resolve,
+        connect: timing.connect,
+        write: timing.write,
+        delay: timing.delay,
+        read: timing.read,
+    }
+}
+
+/// Builds the request that follows a redirect response, or `None` when the response
+/// is not a redirect. 303 always switches to GET, as do 301 and 302 for POST, and the
+/// body goes with it; 307 and 308 repeat the request unchanged. Credentials are not
+/// sent on to another origin, and leaving https for http is refused unless the
+/// request allows a downgrade. With a cookie jar, the hop's cookies are stored and
+/// the `Cookie` header is rebuilt for the next url.
+fn redirect(
+    request: &lib::Request,
+    response: &lib::Response,
+) -> Result<Option<lib::Request>, Error> {
+    if !matches!(response.status, 301 | 302 | 303 | 307 | 308) {
+        return Ok(None);
+    }
+    let Some(location) = response
+        .headers
+        .iter()
+        .find(|header| header.name.eq_ignore_ascii_case("location"))
+    else {
+        return Ok(None);
+    };
+    let from = Url::from(request.url.as_str());
+    let url = from.join(location.value.trim());
+    let to = Url::from(url.as_str());
+    if from.scheme == Scheme::Https && to.scheme == Scheme::Http && !request.downgrade {
+        return Err(Error::Downgrade(url));
+    }
+    let mut next = lib::Request {
+        url,
+        ..request.clone()
+    };
+    if response.status == 303 && request.method != "HEAD"
+        || matches!(response.status, 301 | 302) && request.method == "POST"
+    {
+        next.method = String::from("GET");
+        next.body = None;
+        next.headers.retain(|header| {
+            !["content-type", "content-length"]
+                .iter()
+                .any(|name| header.name.eq_ignore_ascii_case(name))
+        });
+    }
+    if from.scheme != to.scheme || from.host != to.host || from.port != to.port {
+        next.headers.retain(|header| {
+            !["authorization", "cookie", "proxy-authorization"]
+                .iter()
+                .any(|name| header.name.eq_ignore_ascii_case(name))
+        });
+    }
+    if let Some(jar) = &mut next.cookies {
+        for header in &response.headers {
+            if header.name.eq_ignore_ascii_case("set-cookie") {
+                jar.store(&request.url, &header.value);
+            }
+        }
+        next.headers
+            .retain(|header| !header.name.eq_ignore_ascii_case("cookie"));
+        if let Some(cookie) = jar.header(&next.url) {
+            next.headers.push(lib::Header {
+                name: "Cookie".into(),
+                value: cookie,
+            });
+        }
+    }
+    Ok(Some(next))
+}
+
+fn headers(headers: &Headers) -> Vec<lib::Header> {
+    headers
+        .iter()
+        .map(|header| lib::Header {
+            name: header.name.clone(),
+            value: header.value.clone(),
+        })
+        .collect()
+}
+
 #[cfg(test)]
 pub mod tests {
     use axum::Router;
     use axum::body::Bytes;
+    use axum::extract::ws::{CloseFrame, Message, WebSocketUpgrade};
     use axum::extract::{Form, Json, Multipart, Query};
+    use axum::http::StatusCode;
     use axum::http::header::HeaderMap;
-    use axum::routing::{get, post};
+    use axum::response::sse::{Event, Sse};
+    use axum::routing::{any, get, post};
     use serde_json::{Value, json};
     use std::collections::HashMap;
+    use std::convert::Infallible;
     use std::net::ToSocketAddrs;
-    use tokio::net::TcpListener;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, copy_bidirectional};
+    use tokio::net::{TcpListener, TcpStream};
 
     pub async fn start_server(port: u16) {
-        let router = Router::new()
+        let router = router();
+        for addr in ("localhost", port).to_socket_addrs().unwrap() {
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let router = router.clone();
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        }
+    }
+
+    /// Starts the test server on a Unix domain socket at `path`.
+    #[cfg(unix)]
+    pub async fn start_unix_server(path: &Path) {
+        let _ = std::fs::remove_file(path);
+        let listener = tokio::net::UnixListener::bind(path).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router()).await.unwrap() });
+    }
+
+    fn router() -> Router {
+        Router::new()
             .route("/get", get(handle_get))
             .route("/text", post(handle_text))
             .route("/json", post(handle_json))
             .route("/form", post(handle_form))
             .route("/multipart", post(handle_multipart))
-            .route("/file", post(handle_file));
+            .route("/file", post(handle_file))
+            .route("/gzip", get(handle_gzip))
+            .route("/slow", get(handle_slow))
+            .route("/bytes", get(handle_bytes))
+            .route("/redirect", any(handle_redirect))
+            .route("/ws", get(handle_ws))
+            .route("/events", get(handle_events))
+            .route("/events/gzip", get(handle_gzip_events))
+            .route("/upload", post(handle_upload))
+    }
+
+    /// Starts a forwarding and tunnelling proxy that requires the given
+    /// `Proxy-Authorization` value, returning the request lines it has seen.
+    pub async fn start_proxy(port: u16, authorization: &'static str) -> Arc<Mutex<Vec<String>>> {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let listener = TcpListener::bind(("127.0.0.1", port)).await.unwrap();
+        let log = seen.clone();
+        tokio::spawn(async move {
+            loop {
+                let (client, _) = listener.accept().await.unwrap();
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let mut client = BufReader::new(client);
+                    let mut head = String::new();
+                    loop {
+                        let mut line = String::new();
+                        if client.read_line(&mut line).await.unwrap() == 0 {
+                            return;
+                        }
+                        head.push_str(&line);
+                        if line == "\r\n" {
+                            break;
+                        }
+                    }
+                    let line = head.lines().next().unwrap().to_string();
+                    log.lock().unwrap().push(line.clone());
+                    if !head.contains(&format!("Proxy-Authorization: {authorization}\r\n")) {
+                        let denied = "HTTP/1.1 407 Proxy Authentication Required\r\nContent-Length: 0\r\n\r\n";
+                        client.write_all(denied.as_bytes()).await.unwrap();
+                        return;
+                    }
+                    let target = line.split_whitespace().nth(1).unwrap();
+                    let mut upstream = if line.starts_with("CONNECT ") {
+                        let upstream = TcpStream::connect(target).await.unwrap();
+                        let established = "HTTP/1.1 200 Connection Established\r\n\r\n";
+                        client.write_all(established.as_bytes()).await.unwrap();
+                        upstream
+                    } else {
+                        let authority = target.split('/').nth(2).unwrap();
+                        let mut upstream = TcpStream::connect(authority).await.unwrap();
+                        upstream.write_all(head.as_bytes()).await.unwrap();
+                        upstream
+                    };
+                    let _ = copy_bidirectional(&mut client, &mut upstream).await;
+                });
+            }
+        });
+        seen
+    }
+
+    /// Writes a test CA with a `localhost` server certificate and a client certificate
+    /// it signed as PEM files into a new temporary directory.
+    pub fn generate_certificates(name: &str) -> PathBuf {
+        use rcgen::{
+            BasicConstraints, CertificateParams, CertifiedIssuer, DnType, ExtendedKeyUsagePurpose,
+            IsCa, KeyPair,
+        };
+        let dir = std::env::temp_dir().join(format!("basjoofan-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "basjoofan test ca");
+        let ca = CertifiedIssuer::self_signed(params, KeyPair::generate().unwrap()).unwrap();
+        std::fs::write(dir.join("ca.pem"), ca.pem()).unwrap();
+        for (name, usage) in [
+            ("server", ExtendedKeyUsagePurpose::ServerAuth),
+            ("client", ExtendedKeyUsagePurpose::ClientAuth),
+        ] {
+            let key = KeyPair::generate().unwrap();
+            let mut params = CertificateParams::new(vec![String::from("localhost")]).unwrap();
+            params.distinguished_name.push(DnType::CommonName, name);
+            params.extended_key_usages = vec![usage];
+            let certificate = params.signed_by(&key, &ca).unwrap();
+            std::fs::write(dir.join(format!("{name}.pem")), certificate.pem()).unwrap();
+            std::fs::write(dir.join(format!("{name}.key")), key.serialize_pem()).unwrap();
+        }
+        dir
+    }
+
+    /// Starts a TLS server using the certificates in `dir` that answers every request
+    /// with whether the client presented a certificate, requiring one when
+    /// `client_auth` is set.
+    pub async fn start_tls_server(port: u16, dir: &Path, client_auth: bool) {
+        let acceptor = tls_acceptor(dir, client_auth);
         for addr in ("localhost", port).to_socket_addrs().unwrap() {
             let listener = TcpListener::bind(addr).await.unwrap();
-            let router = router.clone();
-            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        let Ok(stream) = acceptor.accept(stream).await else {
+                            return;
+                        };
+                        let client = stream.get_ref().1.peer_certificates().is_some();
+                        let mut stream = BufReader::new(stream);
+                        let mut line = String::new();
+                        while stream.read_line(&mut line).await.unwrap_or(0) > 2 {
+                            line.clear();
+                        }
+                        let body = if client { "client" } else { "anonymous" };
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                            body.len()
+                        );
+                        let _ = stream.write_all(response.as_bytes()).await;
+                        let _ = stream.shutdown().await;
+                    });
+                }
+            });
+        }
+    }
+
+    /// Starts a TLS server using the certificates in `dir` that echoes WebSocket text
+    /// and binary messages.
+    pub async fn start_wss_server(port: u16, dir: &Path) {
+        use futures_util::{SinkExt, StreamExt};
+        let acceptor = tls_acceptor(dir, false);
+        for addr in ("localhost", port).to_socket_addrs().unwrap() {
+            let listener = TcpListener::bind(addr).await.unwrap();
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let acceptor = acceptor.clone();
+                    tokio::spawn(async move {
+                        let Ok(stream) = acceptor.accept(stream).await else {
+                            return;
+                        };
+                        let Ok(mut socket) = tokio_tungstenite::accept_async(stream).await else {
+                            return;
+                        };
+                        while let Some(Ok(message)) = socket.next().await {
+                            if (message.is_text() || message.is_binary())
+                                && socket.send(message).await.is_err()
+                            {
+                                break;
+                            }
+                        }
+                    });
+                }
+            });
         }
     }
 
+    fn tls_acceptor(dir: &Path, client_auth: bool) -> tokio_rustls::TlsAcceptor {
+        use tokio_rustls::rustls::RootCertStore;
+        use tokio_rustls::rustls::ServerConfig;
+        use tokio_rustls::rustls::crypto::ring;
+        use tokio_rustls::rustls::pki_types::pem::PemObject;
+        use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+        use tokio_rustls::rustls::server::WebPkiClientVerifier;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .unwrap();
+        let builder = if client_auth {
+            let mut roots = RootCertStore::empty();
+            roots
+                .add(CertificateDer::from_pem_file(dir.join("ca.pem")).unwrap())
+                .unwrap();
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .unwrap();
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let config = builder
+            .with_single_cert(
+                vec![CertificateDer::from_pem_file(dir.join("server.pem")).unwrap()],
+                PrivateKeyDer::from_pem_file(dir.join("server.key")).unwrap(),
+            )
+            .unwrap();
+        tokio_rustls::TlsAcceptor::from(Arc::new(config))
+    }
+
     fn headers(headers: HeaderMap) -> HashMap<String, String> {
         headers
             .iter()
@@ -209,8 +760,12 @@ pub mod tests {
         mut multipart: Multipart,
     ) -> Json<Value> {
         let mut form = HashMap::new();
+        let mut types = HashMap::new();
         while let Some(field) = multipart.next_field().await.unwrap() {
             let name = field.name().unwrap().to_string();
+            if let Some(content_type) = field.content_type() {
+                types.insert(name.clone(), content_type.to_string());
+            }
             let mut data = field
                 .file_name()
                 .map(|file| format!("@{file}|"))
@@ -218,17 +773,133 @@ pub mod tests {
             data.push_str(&field.text().await.unwrap_or_default());
             form.insert(name, data);
         }
-        Json(json!({ "headers": headers(headers_), "params": params, "form": form }))
+        Json(json!({
+            "headers": headers(headers_),
+            "params": params,
+            "form": form,
+            "types": types
+        }))
     }
 
     async fn handle_file(body: Bytes) -> Json<Value> {
         Json(json!({ "length": body.len(), "bytes": body.to_vec() }))
     }
+
+    /// Rejects a declared length over 1024 bytes without reading the body, so a
+    /// client that expects 100 Continue is refused before it sends one.
+    async fn handle_upload(
+        headers_: HeaderMap,
+        body: axum::body::Body,
+    ) -> Result<Json<Value>, StatusCode> {
+        let length = headers_
+            .get("content-length")
+            .and_then(|length| length.to_str().ok()?.parse::<usize>().ok());
+        if length.is_some_and(|length| length > 1024) {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        Ok(Json(
+            json!({ "length": body.len(), "headers": headers(headers_) }),
+        ))
+    }
+
+    async fn handle_bytes() -> Vec<u8> {
+        vec![0, 159, 146, 150, 255]
+    }
+
+    async fn handle_redirect(
+        Query(params): Query<HashMap<String, String>>,
+    ) -> (StatusCode, [(&'static str, String); 1]) {
+        let status = params["status"].parse().unwrap();
+        (
+            StatusCode::from_u16(status).unwrap(),
+            [("location", params["to"].clone())],
+        )
+    }
+
+    /// Echoes text and binary messages, and closes with 4000 when sent "close".
+    async fn handle_ws(upgrade: WebSocketUpgrade) -> axum::response::Response {
+        upgrade
+            .protocols(["chat"])
+            .on_upgrade(|mut socket| async move {
+                while let Some(Ok(message)) = socket.recv().await {
+                    let reply = match message {
+                        Message::Text(text) if text.as_str() == "close" => {
+                            Message::Close(Some(CloseFrame {
+                                code: 4000,
+                                reason: "bye".into(),
+                            }))
+                        }
+                        Message::Text(_) | Message::Binary(_) => message,
+                        _ => continue,
+                    };
+                    let close = matches!(reply, Message::Close(_));
+                    if socket.send(reply).await.is_err() || close {
+                        break;
+                    }
+                }
+            })
+    }
+
+    /// Sends three messages and a `done` event, then keeps the stream open.
+    async fn handle_events() -> Sse<impl futures_util::Stream<Item = Result<Event, Infallible>>> {
+        use futures_util::StreamExt;
+        let events = [
+            Event::default()
+                .id("1")
+                .data("first")
+                .retry(Duration::from_millis(500)),
+            Event::default().id("2").data("second\nline"),
+            Event::default().event("update").data(r#"{"n":3}"#),
+            Event::default().event("done").data("bye"),
+        ];
+        Sse::new(futures_util::stream::iter(events.map(Ok)).chain(futures_util::stream::pending()))
+    }
+
+    /// Sends two events gzip-encoded, flushing after each, then keeps the stream open.
+    async fn handle_gzip_events() -> axum::response::Response {
+        use futures_util::StreamExt;
+        use std::io::Write;
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        let mut chunks = Vec::new();
+        for event in ["id: 1\ndata: first\n\n", "event: done\ndata: bye\n\n"] {
+            gzip.write_all(event.as_bytes()).unwrap();
+            gzip.flush().unwrap();
+            chunks.push(Ok::<_, Infallible>(Bytes::from(std::mem::take(
+                gzip.get_mut(),
+            ))));
+        }
+        let body = futures_util::stream::iter(chunks).chain(futures_util::stream::pending());
+        axum::response::Response::builder()
+            .header("content-type", "text/event-stream")
+            .header("content-encoding", "gzip")
+            .body(axum::body::Body::from_stream(body))
+            .unwrap()
+    }
+
+    async fn handle_slow() -> &'static str {
+        tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+        "slow"
+    }
+
+    async fn handle_gzip(headers_: HeaderMap) -> ([(&'static str, &'static str); 2], Vec<u8>) {
+        use std::io::Write;
+        let text = json!({ "headers": headers(headers_), "padding": "gzip ".repeat(64) });
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(text.to_string().as_bytes()).unwrap();
+        (
+            [
+                ("content-type", "application/json"),
+                ("content-encoding", "gzip"),
+            ],
+            encoder.finish().unwrap(),
+        )
+    }
 }
 
 macro_rules! http_type {
     ($type: ident $(,$name: ident => $value: literal)+) => {
-        #[derive(Default, Eq, PartialEq)]
+        #[derive(Clone, Copy, Default, Eq, PartialEq)]
         #[non_exhaustive]
         pub enum $type {
             #[default]
@@ -307,9 +978,33 @@ http_type! {
 http_type! {
     Scheme,
     Http => "http",
-    Https => "https"
-    // Ws => "ws",
-    // Wss => "wss"
+    Https => "https",
+    Ws => "ws",
+    Wss => "wss"
+}
+
+impl Method {
+    /// Whether sending the request twice has the same effect as sending it once,
+    /// so it may be retried when its connection fails (RFC 9110 section 9.2.2).
+    pub fn idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get
+                | Method::Head
+                | Method::Put
+                | Method::Delete
+                | Method::Options
+                | Method::Trace
+                | Method::Query
+        )
+    }
+}
+
+impl Scheme {
+    /// Whether connections for the scheme are made over TLS.
+    pub fn secure(&self) -> bool {
+        matches!(self, Scheme::Https | Scheme::Wss)
+    }
 }
 
 #[test]
@@ -323,3 +1018,67 @@ fn test_version_from_str() {
     let version = Version::from("HTTP/2.0");
     println!("version:{version}");
 }
+
+#[test]
+fn test_redirect_refuses_downgrade() {
+    let mut request = lib::Request {
+        method: "GET".into(),
+        url: "https://example.test/old".into(),
+        ..Default::default()
+    };
+    let response = lib::Response {
+        status: 302,
+        headers: vec![lib::Header {
+            name: "Location".into(),
+            value: "http://example.test/new".into(),
+        }],
+        ..Default::default()
+    };
+    let error = redirect(&request, &response).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "refused redirect from https to http://example.test/new"
+    );
+    request.downgrade = true;
+    let next = redirect(&request, &response).unwrap().unwrap();
+    assert_eq!(next.url, "http://example.test/new");
+}
+
+#[test]
+fn test_redirect_rebuilds_cookies_per_hop() {
+    let mut jar = lib::Jar::default();
+    jar.store("http://example.test/", "theme=dark");
+    jar.store("http://example.test/admin/", "admin=1; Path=/admin");
+    let request = lib::Request {
+        method: "GET".into(),
+        url: "http://example.test/admin/login".into(),
+        headers: vec![lib::Header {
+            name: "Cookie".into(),
+            value: "admin=1; theme=dark".into(),
+        }],
+        cookies: Some(jar),
+        ..Default::default()
+    };
+    let response = lib::Response {
+        status: 302,
+        headers: vec![
+            lib::Header {
+                name: "Set-Cookie".into(),
+                value: "session=abc; Path=/".into(),
+            },
+            lib::Header {
+                name: "Location".into(),
+                value: "/home".into(),
+            },
+        ],
+        ..Default::default()
+    };
+    let next = redirect(&request, &response).unwrap().unwrap();
+    let cookies: Vec<_> = next
+        .headers
+        .iter()
+        .filter(|header| header.name.eq_ignore_ascii_case("cookie"))
+        .map(|header| header.value.as_str())
+        .collect();
+    assert_eq!(cookies, ["theme=dark; session=abc"]);
+}
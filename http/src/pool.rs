@@ -0,0 +1,374 @@
+use super::Dialer;
+use super::Error;
+use super::Stream;
+use super::TlsSession;
+use super::Url;
+use super::http2::Session;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use std::time::Duration;
+use std::time::Instant;
+use tokio::io::AsyncBufRead;
+use tokio::io::BufReader;
+use tokio::sync::Notify;
+
+/// Keeps idle HTTP/1.1 connections and open HTTP/2 sessions per host so later
+/// requests can reuse them. Both count against the host's connection limit.
+#[derive(Clone)]
+pub struct Pool {
+    hosts: Arc<Mutex<Hosts>>,
+    sessions: Arc<Mutex<HashMap<String, (Shared, Slot)>>>,
+    idle_timeout: Duration,
+    max_per_host: usize,
+}
+
+type Hosts = HashMap<String, Host>;
+
+#[derive(Default)]
+struct Host {
+    idle: Vec<(BufReader<Stream>, Instant)>,
+    open: usize,
+    /// Requests waiting on `notify` for a slot, which keep the entry from being pruned
+    waiting: usize,
+    notify: Arc<Notify>,
+}
+
+/// An HTTP/2 session that requests to one origin share, with the TLS session its
+/// connection was made over.
+#[derive(Clone)]
+pub struct Shared {
+    pub session: Session,
+    pub tls: Option<TlsSession>,
+    id: u64,
+}
+
+/// What [`Pool::acquire`] hands out: a connection of the request's own, or the
+/// HTTP/2 session another request opened while this one waited.
+pub enum Acquired {
+    Connection(Connection),
+    Session(Shared),
+}
+
+/// A request waiting for a slot of a host, counted until dropped.
+struct Waiting {
+    hosts: Arc<Mutex<Hosts>>,
+    key: String,
+}
+
+/// A place in a host's connection limit, given back when dropped.
+pub struct Slot {
+    hosts: Arc<Mutex<Hosts>>,
+    key: String,
+}
+
+/// A connection checked out of the pool. Dropping it closes the connection;
+/// call [`Pool::release`] to hand it back for reuse.
+pub struct Connection {
+    stream: Option<BufReader<Stream>>,
+    key: String,
+    reused: bool,
+    pool: Pool,
+    open: bool,
+}
+
+impl Pool {
+    pub fn new(idle_timeout: Duration, max_per_host: usize) -> Self {
+        Self {
+            hosts: Arc::default(),
+            sessions: Arc::default(),
+            idle_timeout,
+            max_per_host: max_per_host.max(1),
+        }
+    }
+
+    /// Takes a live idle connection to the url's origin, or opens a new one when the
+    /// host is below its connection limit, waiting for a free slot otherwise. A
+    /// `fresh` connection is always a new one. Otherwise a session kept for the
+    /// origin while waiting is returned instead of a connection.
+    pub async fn acquire(
+        &self,
+        url: &Url,
+        tiomeout: Duration,
+        dialer: &Dialer,
+        fresh: bool,
+    ) -> Result<Acquired, Error> {
+        let key = dialer.key(url);
+        loop {
+            let notify = {
+                let mut hosts = self.hosts.lock().unwrap();
+                let host = hosts.entry(key.clone()).or_default();
+                while !fresh && let Some((mut stream, since)) = host.idle.pop() {
+                    if since.elapsed() < self.idle_timeout && alive(&mut stream) {
+                        return Ok(Acquired::Connection(Connection {
+                            stream: Some(stream),
+                            key,
+                            reused: true,
+                            pool: self.clone(),
+                            open: true,
+                        }));
+                    }
+                    host.open -= 1;
+                }
+                if !fresh && let Some((shared, _)) = self.sessions.lock().unwrap().get(&key) {
+                    // pass the wakeup on to the next request waiting for this host
+                    host.notify.notify_one();
+                    return Ok(Acquired::Session(shared.clone()));
+                }
+                if host.open < self.max_per_host {
+                    host.open += 1;
+                    None
+                } else {
+                    host.waiting += 1;
+                    let waiting = Waiting {
+                        hosts: self.hosts.clone(),
+                        key: key.clone(),
+                    };
+                    Some((host.notify.clone(), waiting))
+                }
+            };
+            match notify {
+                Some((notify, _waiting)) => notify.notified().await,
+                None => {
+                    let mut connection = Connection {
+                        stream: None,
+                        key,
+                        reused: false,
+                        pool: self.clone(),
+                        open: true,
+                    };
+                    let stream = Stream::connect(url, tiomeout, dialer).await?;
+                    connection.stream = Some(BufReader::new(stream));
+                    return Ok(Acquired::Connection(connection));
+                }
+            }
+        }
+    }
+
+    /// Returns a connection to the pool, keeping it open only when `reusable`.
+    pub fn release(&self, mut connection: Connection, reusable: bool) {
+        connection.open = false;
+        if let Some(stream) = connection.stream.take() {
+            let mut hosts = self.hosts.lock().unwrap();
+            if let Some(host) = hosts.get_mut(&connection.key) {
+                if reusable && stream.buffer().is_empty() {
+                    host.idle.push((stream, Instant::now()));
+                } else {
+                    host.open -= 1;
+                }
+                host.notify.notify_one();
+            }
+            prune(&mut hosts, &connection.key);
+        }
+    }
+
+    /// Returns the open HTTP/2 session to the url's origin, if there is one.
+    pub fn session(&self, url: &Url, dialer: &Dialer) -> Option<Shared> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions
+            .get(&dialer.key(url))
+            .map(|(shared, _)| shared.clone())
+    }
+
+    /// Keeps an HTTP/2 session for later requests to the same origin, holding its
+    /// connection's slot for as long as it is kept. When another request kept a
+    /// session for the origin first, that one stays and the slot is handed back
+    /// to be held while this session is used once.
+    pub fn keep(
+        &self,
+        url: &Url,
+        dialer: &Dialer,
+        session: Session,
+        tls: Option<TlsSession>,
+        slot: Slot,
+    ) -> (Shared, Option<Slot>) {
+        static IDS: AtomicU64 = AtomicU64::new(0);
+        let shared = Shared {
+            session,
+            tls,
+            id: IDS.fetch_add(1, Ordering::Relaxed),
+        };
+        let key = dialer.key(url);
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions.entry(key.clone()) {
+                Entry::Occupied(_) => return (shared, Some(slot)),
+                Entry::Vacant(entry) => entry.insert((shared.clone(), slot)),
+            };
+        }
+        // requests waiting for a slot can use the session instead
+        if let Some(host) = self.hosts.lock().unwrap().get(&key) {
+            host.notify.notify_one();
+        }
+        (shared, None)
+    }
+
+    /// Drops a session whose connection has gone away, unless it was already
+    /// replaced by a newer one.
+    pub fn forget(&self, url: &Url, dialer: &Dialer, shared: &Shared) {
+        let key = dialer.key(url);
+        let removed = {
+            let mut sessions = self.sessions.lock().unwrap();
+            match sessions.get(&key) {
+                Some((kept, _)) if kept.id == shared.id => sessions.remove(&key),
+                _ => None,
+            }
+        };
+        // the slot locks the hosts, which must not happen while holding the sessions
+        drop(removed);
+    }
+
+    fn close(&self, key: &str) {
+        close(&self.hosts, key);
+    }
+}
+
+fn close(hosts: &Mutex<Hosts>, key: &str) {
+    let mut hosts = hosts.lock().unwrap();
+    if let Some(host) = hosts.get_mut(key) {
+        host.open -= 1;
+        host.notify.notify_one();
+    }
+    prune(&mut hosts, key);
+}
+
+/// Removes a host's entry once it has no connections left and no requests waiting
+/// on its notify, so the map does not keep every host ever contacted.
+fn prune(hosts: &mut Hosts, key: &str) {
+    if hosts
+        .get(key)
+        .is_some_and(|host| host.open == 0 && host.idle.is_empty() && host.waiting == 0)
+    {
+        hosts.remove(key);
+    }
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(30), 6)
+    }
+}
+
+impl Connection {
+    pub fn stream(&mut self) -> &mut BufReader<Stream> {
+        self.stream.as_mut().expect("connection already released")
+    }
+
+    pub fn reused(&self) -> bool {
+        self.reused
+    }
+
+    /// Takes the stream out of the pool for a protocol that manages the connection
+    /// itself, along with the slot it holds in the host's connection limit.
+    pub fn detach(mut self) -> (Stream, Slot) {
+        self.open = false;
+        let slot = Slot {
+            hosts: self.pool.hosts.clone(),
+            key: self.key.clone(),
+        };
+        let stream = self
+            .stream
+            .take()
+            .expect("connection already released")
+            .into_inner();
+        (stream, slot)
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        if self.open {
+            self.pool.close(&self.key);
+        }
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if let Some(host) = hosts.get_mut(&self.key) {
+            host.waiting -= 1;
+        }
+        prune(&mut hosts, &self.key);
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        close(&self.hosts, &self.key);
+    }
+}
+
+/// An idle connection must have nothing to read: readable data or end of file means
+/// the peer has sent something unexpected or closed it.
+fn alive(stream: &mut BufReader<Stream>) -> bool {
+    let mut context = Context::from_waker(Waker::noop());
+    matches!(Pin::new(stream).poll_fill_buf(&mut context), Poll::Pending)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::Acquired;
+    use super::Pool;
+    use crate::Dialer;
+    use crate::Url;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_release_prunes_hosts_without_connections() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::from(format!("http://{}/", listener.local_addr().unwrap()).as_str());
+        let pool = Pool::default();
+        let dialer = Dialer::default();
+        let Ok(Acquired::Connection(connection)) = pool
+            .acquire(&url, Duration::from_secs(1), &dialer, false)
+            .await
+        else {
+            panic!("no connection");
+        };
+        assert_eq!(pool.hosts.lock().unwrap().len(), 1);
+        pool.release(connection, false);
+        assert!(pool.hosts.lock().unwrap().is_empty());
+        drop(
+            pool.acquire(&url, Duration::from_secs(1), &dialer, false)
+                .await
+                .unwrap(),
+        );
+        assert!(pool.hosts.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_release_wakes_every_waiter_of_a_closed_connection() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::from(format!("http://{}/", listener.local_addr().unwrap()).as_str());
+        let pool = Pool::new(Duration::from_secs(30), 1);
+        let dialer = Dialer::default();
+        let acquire = || {
+            let (pool, url, dialer) = (pool.clone(), url.clone(), dialer.clone());
+            async move {
+                let Ok(Acquired::Connection(connection)) = pool
+                    .acquire(&url, Duration::from_secs(1), &dialer, false)
+                    .await
+                else {
+                    panic!("no connection");
+                };
+                tokio::task::yield_now().await;
+                // as after a response with Connection: close
+                pool.release(connection, false);
+            }
+        };
+        let requests = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures_util::future::join3(acquire(), acquire(), acquire()),
+        );
+        assert!(requests.await.is_ok());
+        assert!(pool.hosts.lock().unwrap().is_empty());
+    }
+}
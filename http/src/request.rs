@@ -55,58 +55,26 @@ impl Request {
                     content = Content::Bytes(bytes);
                 }
                 Some("multipart/form-data") => {
-                    let mut boundary = String::from("FormDataBoundary");
-                    let rng = rand::rng();
-                    boundary.extend(
-                        rng.sample_iter(rand::distr::Alphanumeric)
-                            .take(boundary.len())
-                            .map(char::from),
-                    );
-                    headers.replace(
-                        "content-type",
-                        format!("multipart/form-data; boundary={boundary}"),
-                    );
                     let mut parts = Vec::new();
                     for line in lines.by_ref() {
                         if let Some((name, value)) = line.trim().split_once(':') {
-                            let (name, value) = (name.trim(), value.trim());
-                            let mut bytes = Vec::new();
-                            bytes.append(&mut format!("--{boundary}\r\n").into_bytes());
-                            if value.starts_with("@") {
-                                let path = Path::new(&value[1..value.len()]);
-                                let file = File::open(path).await?;
-                                let metadata = file.metadata().await?;
-                                bytes.append(
-                                    &mut format!(
-                                        "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n",
-                                        name,
-                                        path.display()
-                                    )
-                                    .into_bytes(),
-                                );
-                                if let Some(mime) = mime::from_path(path) {
-                                    bytes.append(
-                                        &mut format!("Content-Type: {mime}\r\n\r\n").into_bytes(),
-                                    );
-                                };
-                                length += bytes.len() + metadata.len() as usize + 2;
-                                parts.push(Part::Bytes(bytes));
-                                parts.push(Part::File(file));
-                                parts.push(Part::Bytes(vec![b'\r', b'\n']));
-                            } else {
-                                bytes.append(
-                                    &mut format!("Content-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n").into_bytes(),
-                                );
-                                length += bytes.len();
-                                parts.push(Part::Bytes(bytes));
-                            }
+                            let value = value.trim();
+                            parts.push(lib::Part {
+                                name: name.trim().to_string(),
+                                content: match value.strip_prefix('@') {
+                                    Some(path) => lib::Content::File(path.to_string()),
+                                    None => lib::Content::Text(value.to_string()),
+                                },
+                                filename: None,
+                                content_type: None,
+                            });
                             body.push_str(line);
                         }
                     }
-                    let bytes = format!("--{boundary}--\r\n").into_bytes();
-                    length += bytes.len();
-                    parts.push(Part::Bytes(bytes));
-                    content = Content::Parts(parts)
+                    let (parts, size, content_type) = multipart(&parts).await?;
+                    headers.replace("content-type", content_type);
+                    length = size;
+                    content = parts;
                 }
                 _ => {
                     body = String::from_iter(lines);
@@ -135,26 +103,190 @@ impl Request {
         }
     }
 
+    /// Replaces the body with the multipart/form-data encoding of `parts`. File
+    /// contents are not read here but streamed when the request is written.
+    pub async fn multipart(&mut self, parts: &[lib::Part]) -> Result<Content, std::io::Error> {
+        let (content, length, content_type) = multipart(parts).await?;
+        self.headers.replace("content-type", content_type);
+        self.headers.replace("content-length", length.to_string());
+        self.body = parts
+            .iter()
+            .map(|part| match &part.content {
+                lib::Content::File(path) => format!("{}: @{path}", part.name),
+                lib::Content::Text(text) => format!("{}: {text}", part.name),
+                lib::Content::Parts(_) => format!("{}: ...", part.name),
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        Ok(content)
+    }
+
+    /// Writes the request head and body. `absolute` writes the request target as a
+    /// full url, the form proxies expect.
     pub async fn write<W: AsyncWrite + Unpin>(
         &mut self,
         mut writer: W,
         content: Content,
+        absolute: bool,
+    ) -> Result<(), std::io::Error> {
+        self.write_head(&mut writer, absolute).await?;
+        self.write_body(&mut writer, content).await
+    }
+
+    /// Writes the request line and headers. `Content-Length` is left out of a
+    /// chunked request.
+    pub async fn write_head<W: AsyncWrite + Unpin>(
+        &mut self,
+        mut writer: W,
+        absolute: bool,
     ) -> Result<(), std::io::Error> {
+        let target = if absolute {
+            self.url.to_string()
+        } else {
+            self.url.path.clone()
+        };
         writer
-            .write_all(format!("{} {} {}\r\n", self.method, self.url.path, self.version).as_bytes())
+            .write_all(format!("{} {} {}\r\n", self.method, target, self.version).as_bytes())
             .await?;
+        let chunked = self.chunked();
         for header in self.headers.iter() {
+            if chunked && header.name.eq_ignore_ascii_case("content-length") {
+                continue;
+            }
             writer
                 .write_all(format!("{}: {}\r\n", header.name, header.value).as_bytes())
                 .await?;
         }
         writer.write_all("\r\n".as_bytes()).await?;
-        content.write(&mut writer).await?;
-        writer.flush().await?;
-        Ok(())
+        writer.flush().await
+    }
+
+    /// Writes the body, in chunks when the request is chunked.
+    pub async fn write_body<W: AsyncWrite + Unpin>(
+        &self,
+        mut writer: W,
+        content: Content,
+    ) -> Result<(), std::io::Error> {
+        if self.chunked() {
+            content.write_chunked(&mut writer).await?;
+        } else {
+            content.write(&mut writer).await?;
+        }
+        writer.flush().await
+    }
+
+    /// Whether the request sets `Transfer-Encoding: chunked`, so its body is sent
+    /// in chunks without a length known up front.
+    pub fn chunked(&self) -> bool {
+        self.headers
+            .get("transfer-encoding")
+            .and_then(|value| value.rsplit(',').next())
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"))
+    }
+
+    /// Whether the request asks the server to confirm with 100 Continue before the
+    /// body is sent.
+    pub fn expects_continue(&self) -> bool {
+        self.headers
+            .get("expect")
+            .is_some_and(|value| value.trim().eq_ignore_ascii_case("100-continue"))
     }
 }
 
+/// Encodes form fields under a random boundary, returning the content, its length
+/// and the Content-Type value that names the boundary.
+async fn multipart(parts: &[lib::Part]) -> Result<(Content, usize, String), std::io::Error> {
+    let mut boundary = String::from("FormDataBoundary");
+    let rng = rand::rng();
+    boundary.extend(
+        rng.sample_iter(rand::distr::Alphanumeric)
+            .take(boundary.len())
+            .map(char::from),
+    );
+    let mut content = Vec::new();
+    let mut length = 0;
+    for part in parts {
+        let mut head = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"",
+            quote(&part.name)
+        );
+        match &part.content {
+            lib::Content::Text(text) => {
+                if let Some(filename) = &part.filename {
+                    head.push_str(&format!("; filename=\"{}\"", quote(filename)));
+                }
+                if let Some(content_type) = &part.content_type {
+                    let content_type = header_value(part, content_type)?;
+                    head.push_str(&format!("\r\nContent-Type: {content_type}"));
+                }
+                let bytes = format!("{head}\r\n\r\n{text}\r\n").into_bytes();
+                length += bytes.len();
+                content.push(Part::Bytes(bytes));
+            }
+            lib::Content::File(path) => {
+                let path = Path::new(path);
+                let file = File::open(path).await?;
+                let size = file.metadata().await?.len() as usize;
+                let filename = part.filename.clone().unwrap_or_else(|| {
+                    path.file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                        .unwrap_or_default()
+                });
+                let content_type = match &part.content_type {
+                    Some(content_type) => header_value(part, content_type)?,
+                    None => mime::from_path(path).unwrap_or("application/octet-stream"),
+                };
+                let bytes = format!(
+                    "{head}; filename=\"{}\"\r\nContent-Type: {content_type}\r\n\r\n",
+                    quote(&filename)
+                )
+                .into_bytes();
+                length += bytes.len() + size + 2;
+                content.push(Part::Bytes(bytes));
+                content.push(Part::File(file));
+                content.push(Part::Bytes(b"\r\n".to_vec()));
+            }
+            lib::Content::Parts(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("multipart field '{}' cannot hold parts", part.name),
+                ));
+            }
+        }
+    }
+    let bytes = format!("--{boundary}--\r\n").into_bytes();
+    length += bytes.len();
+    content.push(Part::Bytes(bytes));
+    Ok((
+        Content::Parts(content),
+        length,
+        format!("multipart/form-data; boundary={boundary}"),
+    ))
+}
+
+/// Checks that a part's Content-Type stays on its own header line, since a line
+/// break in it would start another header or end the part's head.
+fn header_value<'a>(part: &lib::Part, value: &'a str) -> Result<&'a str, std::io::Error> {
+    match value.chars().any(|c| c.is_control() && c != '\t') {
+        false => Ok(value),
+        true => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!(
+                "multipart field '{}' has an invalid content type",
+                quote(&part.name)
+            ),
+        )),
+    }
+}
+
+/// Escapes a name or filename for a quoted Content-Disposition parameter the way
+/// browsers do, percent-encoding quotes and line breaks.
+fn quote(text: &str) -> String {
+    text.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
 #[cfg(test)]
 pub mod tests {
     use super::AsyncWriteExt;
@@ -167,7 +299,7 @@ pub mod tests {
     Host: domain"#;
         let (mut request, content) = Request::from(message).await.unwrap();
         let mut writer = tokio::io::stdout();
-        request.write(&mut writer, content).await.unwrap();
+        request.write(&mut writer, content, false).await.unwrap();
         writer.write_all("\r\n".as_bytes()).await.unwrap();
         assert_eq!("GET", request.method.as_ref());
     }
@@ -183,11 +315,54 @@ pub mod tests {
     f: @src/lib.rs"#;
         let (mut request, content) = Request::from(message).await.unwrap();
         let mut writer = tokio::io::stdout();
-        request.write(&mut writer, content).await.unwrap();
+        request.write(&mut writer, content, false).await.unwrap();
         writer.write_all("\r\n".as_bytes()).await.unwrap();
         assert_eq!("POST", request.method.as_ref());
     }
 
+    #[tokio::test]
+    async fn test_multipart_keeps_script_values_inside_the_part_head() {
+        let part = |name: &str, filename: Option<&str>, content_type: Option<&str>| lib::Part {
+            name: name.into(),
+            content: lib::Content::Text("value".into()),
+            filename: filename.map(str::to_owned),
+            content_type: content_type.map(str::to_owned),
+        };
+        let (content, _, content_type) = super::multipart(&[part(
+            "a\"\r\nX-Injected: 1",
+            Some("b.txt\"\r\n\r\nbody"),
+            Some("text/plain; charset=utf-8"),
+        )])
+        .await
+        .unwrap();
+        let super::Content::Parts(parts) = content else {
+            panic!("multipart content is split into parts");
+        };
+        let super::Part::Bytes(head) = &parts[0] else {
+            panic!("a text part is held in memory");
+        };
+        let boundary = content_type.split_once("boundary=").unwrap().1;
+        assert_eq!(
+            String::from_utf8_lossy(head),
+            format!(
+                "--{boundary}\r\nContent-Disposition: form-data; name=\"a%22%0D%0AX-Injected: 1\"; \
+                 filename=\"b.txt%22%0D%0A%0D%0Abody\"\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nvalue\r\n"
+            )
+        );
+
+        for content_type in ["text/plain\r\nX-Injected: 1", "text/plain\n\n--boundary"] {
+            let error = super::multipart(&[part("a", None, Some(content_type))])
+                .await
+                .err()
+                .unwrap();
+            assert_eq!(std::io::ErrorKind::InvalidInput, error.kind());
+            assert!(
+                error.to_string().contains("invalid content type"),
+                "{error}"
+            );
+        }
+    }
+
     #[tokio::test]
     async fn test_from_message_header_empty_value() {
         let message = r#"
@@ -199,7 +374,7 @@ pub mod tests {
     d:"#;
         let (mut request, content) = Request::from(message).await.unwrap();
         let mut writer = tokio::io::stdout();
-        request.write(&mut writer, content).await.unwrap();
+        request.write(&mut writer, content, false).await.unwrap();
         writer.write_all("\r\n".as_bytes()).await.unwrap();
         println!("{:?}", request.headers);
         assert_eq!("GET", request.method.as_ref());
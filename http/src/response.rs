@@ -1,67 +1,349 @@
 use super::Headers;
+use super::Method;
 use super::Response;
-use super::Stream;
+use super::encoding;
+use super::event;
+use super::event::Events;
+use tokio::io::AsyncBufRead;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::AsyncReadExt;
-use tokio::io::BufReader;
-use tokio::io::ReadHalf;
 
 impl Response {
     /// Converts a stream to an http response.
-    pub async fn from(
-        reader: &mut ReadHalf<Stream>,
+    pub async fn from<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        method: &Method,
         callback: Option<impl FnMut()>,
     ) -> Result<Response, std::io::Error> {
-        let mut reader = BufReader::new(reader);
-        let mut buf = Vec::with_capacity(1);
-        reader.read_exact(&mut buf).await?;
+        Self::from_events(reader, method, &Events::default(), callback).await
+    }
+
+    /// Converts a stream to an http response, reading a `text/event-stream` body as
+    /// it arrives and only until `events` says to stop.
+    pub async fn from_events<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        method: &Method,
+        events: &Events,
+        callback: Option<impl FnMut()>,
+    ) -> Result<Response, std::io::Error> {
+        if reader.fill_buf().await?.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "connection closed before response",
+            ));
+        }
         if let Some(mut callback) = callback {
             callback()
         }
-        let mut line = String::from_utf8(buf).unwrap_or_default();
-        reader.read_line(&mut line).await?;
-        let mut splits = line.split_whitespace();
-        let version = parse::<String>(splits.next());
-        let status = parse::<u16>(splits.next());
-        let reason = parse::<String>(splits.next());
-        // headers
-        let mut headers = Headers::default();
-        let mut length = None;
-        loop {
-            let mut line = String::new();
-            reader.read_line(&mut line).await?;
-            if line.trim().is_empty() {
-                break;
-            } else if let Some((name, value)) = line.split_once(':') {
-                headers.insert(name.trim().to_string(), value.trim().to_string());
-                if length.is_none() && name.trim().to_lowercase() == "content-length" {
-                    length = value.trim().parse::<usize>().ok();
-                }
+        let head = loop {
+            let head = read_head(reader).await?;
+            // skip interim responses such as 100 Continue
+            if !(100..200).contains(&head.status) || head.status == 101 {
+                break head;
             }
+        };
+        Self::from_head(reader, method, events, head).await
+    }
+
+    /// Reads the body that follows a final response head.
+    pub(crate) async fn from_head<R: AsyncBufRead + Unpin>(
+        reader: &mut R,
+        method: &Method,
+        events: &Events,
+        head: Head,
+    ) -> Result<Response, std::io::Error> {
+        let Head {
+            version,
+            status,
+            reason,
+            headers,
+        } = head;
+        let bodiless = *method == Method::Head || matches!(status, 100..200 | 204 | 304);
+        let mut codings = headers
+            .get("transfer-encoding")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|coding| !coding.is_empty())
+            .collect::<Vec<_>>();
+        let chunked = codings
+            .last()
+            .is_some_and(|coding| coding.eq_ignore_ascii_case("chunked"));
+        if chunked {
+            codings.pop();
+        }
+        if let Some(coding) = codings
+            .iter()
+            .find(|coding| !encoding::supports(coding))
+            .filter(|_| !bodiless)
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("unsupported transfer coding: {coding}"),
+            ));
         }
+        // a transfer coding replaces the declared length
+        let length = headers
+            .get("content-length")
+            .filter(|_| headers.get("transfer-encoding").is_none())
+            .and_then(|value| value.parse::<usize>().ok());
         let mut body = Vec::new();
-        match length {
-            Some(length) => {
+        let mut trailers = Headers::default();
+        let mut parser = event::Parser::default();
+        let mut complete = true;
+        let stream = is_event_stream(&headers);
+        // an event stream is decoded as it arrives, unless a transfer coding wraps it
+        let mut decoder = match stream && codings.is_empty() {
+            true => encoding::Decoder::new(headers.get("content-encoding").unwrap_or_default())
+                .map(|decoder| (decoder, Vec::new())),
+            false => None,
+        };
+        let incremental = decoder.is_some();
+        match (bodiless, chunked, length) {
+            (true, _, _) => {}
+            (false, _, _) if incremental => {
+                let read = read_events(
+                    reader,
+                    chunked,
+                    length,
+                    &mut body,
+                    &mut decoder,
+                    &mut parser,
+                    events,
+                );
+                (complete, trailers) = match events.tiomeout {
+                    Some(tiomeout) => tokio::time::timeout(tiomeout, read)
+                        .await
+                        .unwrap_or(Ok((false, Headers::default())))?,
+                    None => read.await?,
+                };
+            }
+            (false, true, _) => trailers = read_chunked(reader, &mut body).await?,
+            (false, false, Some(length)) => {
+                within_limit(&body, length)?;
                 body.resize(length, u8::MIN);
-                reader.read_exact(&mut body).await?
+                reader.read_exact(&mut body).await?;
+            }
+            (false, false, None) => {
+                (&mut *reader)
+                    .take(encoding::LIMIT + 1)
+                    .read_to_end(&mut body)
+                    .await?;
+                within_limit(&body, 0)?;
             }
-            None => reader.read_to_end(&mut body).await?,
         };
-        // TODO other encoding
-        let body = match String::from_utf8(body) {
-            Ok(body) => body,
-            Err(error) => error.to_string(),
+        let connection = headers.get("connection").unwrap_or_default();
+        let persistent = match version.as_str() {
+            "HTTP/1.1" => !has_token(connection, "close"),
+            "HTTP/1.0" => has_token(connection, "keep-alive"),
+            _ => false,
+        };
+        let keep_alive =
+            persistent && complete && status != 101 && (bodiless || chunked || length.is_some());
+        let transferred = body.len();
+        if !bodiless && !codings.is_empty() {
+            encoding::decode(&codings.join(", "), &mut body)?;
+        }
+        // a stream cut short at the last wanted event keeps what decoded so far
+        let finished = match decoder.as_mut() {
+            Some((decoder, decoded)) if complete && !bodiless => decoder
+                .finish()
+                .map(|data| {
+                    parser.feed(&data);
+                    decoded.extend(data);
+                })
+                .is_ok(),
+            Some(_) => true,
+            None => false,
+        };
+        let (encoding, undecoded) = match decoder {
+            Some((decoder, decoded)) if finished => {
+                body = decoded;
+                (decoder.names(), None)
+            }
+            _ => encoding::decode_content(headers.get("content-encoding"), &mut body),
         };
+        if stream && !incremental && undecoded.is_none() {
+            parser.feed(&body);
+        }
+        if let Some(end) = events.end(&parser.events) {
+            parser.events.truncate(end);
+        }
         Ok(Response {
             version,
             status,
             reason,
             headers,
             body,
+            trailers,
+            encoding,
+            undecoded,
+            transferred,
+            keep_alive,
+            reused: false,
+            tls: None,
+            events: parser.events,
         })
     }
 }
 
+/// The status line and headers of one response, final or interim.
+pub(crate) struct Head {
+    pub version: String,
+    pub status: u16,
+    pub reason: String,
+    pub headers: Headers,
+}
+
+/// Reads a status line and the header lines after it.
+pub(crate) async fn read_head<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+) -> Result<Head, std::io::Error> {
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    let mut splits = line.split_whitespace();
+    Ok(Head {
+        version: parse::<String>(splits.next()),
+        status: parse::<u16>(splits.next()),
+        reason: parse::<String>(splits.next()),
+        headers: read_headers(reader).await?,
+    })
+}
+
+/// Whether the response carries a `text/event-stream` body.
+pub(crate) fn is_event_stream(headers: &Headers) -> bool {
+    headers.get("content-type").is_some_and(|value| {
+        value
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .eq_ignore_ascii_case("text/event-stream")
+    })
+}
+
+/// Reads an event stream body piece by piece, parsing events as they arrive, until
+/// the body ends or `events` is satisfied. Returns whether the whole body was read,
+/// and the trailers of a chunked body.
+async fn read_events<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    chunked: bool,
+    mut length: Option<usize>,
+    body: &mut Vec<u8>,
+    decoder: &mut Option<(encoding::Decoder, Vec<u8>)>,
+    parser: &mut event::Parser,
+    events: &Events,
+) -> Result<(bool, Headers), std::io::Error> {
+    loop {
+        let start = body.len();
+        if chunked {
+            if !read_chunk(reader, body).await? {
+                return Ok((true, read_headers(reader).await?));
+            }
+        } else {
+            let buffer = reader.fill_buf().await?;
+            let size = buffer.len().min(length.unwrap_or(usize::MAX));
+            if size == 0 {
+                return Ok((true, Headers::default()));
+            }
+            within_limit(body, size)?;
+            body.extend_from_slice(&buffer[..size]);
+            reader.consume(size);
+            length = length.map(|length| length - size);
+        }
+        let Some((stage, decoded)) = decoder.as_mut() else {
+            continue;
+        };
+        match stage.feed(&body[start..]) {
+            Ok(data) => {
+                parser.feed(&data);
+                decoded.extend(data);
+            }
+            // left for decode_content to report once the body is read
+            Err(_) => *decoder = None,
+        }
+        if events.end(&parser.events).is_some() {
+            return Ok((length == Some(0), Headers::default()));
+        }
+    }
+}
+
+fn has_token(value: &str, token: &str) -> bool {
+    value
+        .split(',')
+        .any(|item| item.trim().eq_ignore_ascii_case(token))
+}
+
+/// Reads header lines up to and including the empty line that ends them.
+async fn read_headers<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<Headers, std::io::Error> {
+    let mut headers = Headers::default();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 || line.trim().is_empty() {
+            break;
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_string(), value.trim().to_string());
+        }
+    }
+    Ok(headers)
+}
+
+/// Decodes a chunked body into `body`, discarding chunk extensions and returning the trailers.
+async fn read_chunked<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    body: &mut Vec<u8>,
+) -> Result<Headers, std::io::Error> {
+    while read_chunk(reader, body).await? {}
+    read_headers(reader).await
+}
+
+/// Appends one chunk to `body`, returning false for the last, empty chunk. The chunk
+/// is read in pieces, so its declared size is never allocated up front.
+async fn read_chunk<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    body: &mut Vec<u8>,
+) -> Result<bool, std::io::Error> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await? == 0 {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+    }
+    let size = line.split(';').next().unwrap_or_default().trim();
+    let size = usize::from_str_radix(size, 16).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("invalid chunk size: {}", line.trim()),
+        )
+    })?;
+    if size == 0 {
+        return Ok(false);
+    }
+    within_limit(body, size)?;
+    let read = (&mut *reader).take(size as u64).read_to_end(body).await?;
+    if read < size {
+        return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+    }
+    let mut line = String::new();
+    reader.read_line(&mut line).await?;
+    if !line.trim().is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "missing chunk terminator",
+        ));
+    }
+    Ok(true)
+}
+
+/// Fails when `size` more bytes would take the raw body past `encoding::LIMIT`.
+fn within_limit(body: &[u8], size: usize) -> Result<(), std::io::Error> {
+    match (body.len() as u64).saturating_add(size as u64) > encoding::LIMIT {
+        true => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("body exceeds {} bytes", encoding::LIMIT),
+        )),
+        false => Ok(()),
+    }
+}
+
 fn parse<T: std::str::FromStr + std::default::Default>(str: Option<&str>) -> T {
     match str {
         Some(str) => str.parse::<T>().unwrap_or_default(),
@@ -71,8 +353,10 @@ fn parse<T: std::str::FromStr + std::default::Default>(str: Option<&str>) -> T {
 
 #[cfg(test)]
 pub mod tests {
+    use super::Method;
     use super::Response;
-    use super::Stream;
+    use crate::Stream;
+    use tokio::io::BufReader;
 
     #[tokio::test]
     async fn test_from_message_read_exact() {
@@ -90,13 +374,13 @@ Access-Control-Allow-Credentials: true
 }
 "#;
         let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
-        let (mut reader, _) = tokio::io::split(stream);
-        let response = Response::from(&mut reader, None::<Box<dyn FnMut()>>)
+        let mut reader = BufReader::new(stream);
+        let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
             .await
             .unwrap();
         assert_eq!(200, response.status);
         assert_eq!(7, response.headers.len());
-        assert_eq!("{\n  \"origin\": \"122.9.3.166\"\n}\n", response.body);
+        assert_eq!(b"{\n  \"origin\": \"122.9.3.166\"\n}\n", &response.body[..]);
     }
 
     #[tokio::test]
@@ -115,12 +399,149 @@ Access-Control-Allow-Credentials: true
 }
 "#;
         let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
-        let (mut reader, _) = tokio::io::split(stream);
-        let response = Response::from(&mut reader, None::<Box<dyn FnMut()>>)
+        let mut reader = BufReader::new(stream);
+        let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
             .await
             .unwrap();
         assert_eq!(200, response.status);
         assert_eq!(7, response.headers.len());
-        assert_eq!("{\n  \"origin\": \"122.9.3.166\"\n}\n", response.body);
+        assert_eq!(b"{\n  \"origin\": \"122.9.3.166\"\n}\n", &response.body[..]);
+    }
+
+    #[tokio::test]
+    async fn test_from_message_chunked() {
+        let message = "HTTP/1.1 200 OK\r\n\
+Content-Type: text/plain\r\n\
+Transfer-Encoding: chunked\r\n\
+Trailer: X-Checksum\r\n\
+\r\n\
+5;name=value\r\n\
+hello\r\n\
+7\r\n\
+, world\r\n\
+0\r\n\
+X-Checksum: 42\r\n\
+\r\n\
+HTTP/1.1 204 No Content\r\n";
+        let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+        let mut reader = BufReader::new(stream);
+        let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap();
+        assert_eq!(200, response.status);
+        assert_eq!(b"hello, world", &response.body[..]);
+        assert_eq!(Some("42"), response.trailers.get("x-checksum"));
+    }
+
+    #[tokio::test]
+    async fn test_from_message_transfer_codings() {
+        use std::io::Write;
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"hello, world").unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut message = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: gzip, chunked\r\n\r\n".to_vec();
+        message.extend_from_slice(format!("{:x}\r\n", gzip.len()).as_bytes());
+        message.extend_from_slice(&gzip);
+        message.extend_from_slice(b"\r\n0\r\n\r\n");
+        let stream = Stream::Mock(std::io::Cursor::new(message));
+        let mut reader = BufReader::new(stream);
+        let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap();
+        assert_eq!(b"hello, world", &response.body[..]);
+        assert_eq!(gzip.len(), response.transferred);
+        assert!(response.keep_alive);
+
+        let message = "HTTP/1.1 200 OK\r\nTransfer-Encoding: compress, chunked\r\n\r\n0\r\n\r\n";
+        let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+        let mut reader = BufReader::new(stream);
+        let error = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+        assert!(error.to_string().contains("compress"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn test_from_message_decodes_gzip_event_stream() {
+        use std::io::Write;
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gzip.write_all(b"id: 1\ndata: first\n\nevent: done\ndata: bye\n\n")
+            .unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut message = b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+Content-Encoding: gzip\r\nTransfer-Encoding: chunked\r\n\r\n"
+            .to_vec();
+        for piece in gzip.chunks(8) {
+            message.extend_from_slice(format!("{:x}\r\n", piece.len()).as_bytes());
+            message.extend_from_slice(piece);
+            message.extend_from_slice(b"\r\n");
+        }
+        message.extend_from_slice(b"0\r\n\r\n");
+        let stream = Stream::Mock(std::io::Cursor::new(message));
+        let mut reader = BufReader::new(stream);
+        let events = crate::event::Events::default();
+        let response =
+            Response::from_events(&mut reader, &Method::Get, &events, None::<Box<dyn FnMut()>>)
+                .await
+                .unwrap();
+        let names: Vec<_> = response.events.iter().map(|e| &e.event).collect();
+        assert_eq!(names, ["message", "done"]);
+        assert_eq!("gzip", response.encoding);
+        assert!(response.body.starts_with(b"id: 1\ndata: first"));
+        assert_eq!(gzip.len(), response.transferred);
+        assert!(response.keep_alive);
+    }
+
+    #[tokio::test]
+    async fn test_from_message_keeps_undecodable_body() {
+        let message =
+            "HTTP/1.1 200 OK\r\nContent-Encoding: gzip\r\nContent-Length: 8\r\n\r\nnot gzip";
+        let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+        let mut reader = BufReader::new(stream);
+        let response = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap();
+        assert_eq!(b"not gzip", &response.body[..]);
+        assert_eq!("", response.encoding);
+        assert!(response.undecoded.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_from_message_chunked_invalid_size() {
+        let message = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+        let mut reader = BufReader::new(stream);
+        let error = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidData, error.kind());
+    }
+
+    #[tokio::test]
+    async fn test_from_message_rejects_body_over_limit() {
+        let messages = [
+            "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nffffffffffffffff\r\nabc",
+            "HTTP/1.1 200 OK\r\nContent-Length: 18446744073709551615\r\n\r\nabc",
+        ];
+        for message in messages {
+            let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+            let mut reader = BufReader::new(stream);
+            let error = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+                .await
+                .unwrap_err();
+            assert_eq!(std::io::ErrorKind::InvalidData, error.kind(), "{message}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_from_message_chunked_truncated() {
+        let message = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\na\r\nabc";
+        let stream = Stream::Mock(std::io::Cursor::new(message.as_bytes().to_owned()));
+        let mut reader = BufReader::new(stream);
+        let error = Response::from(&mut reader, &Method::Get, None::<Box<dyn FnMut()>>)
+            .await
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::UnexpectedEof, error.kind());
     }
 }
@@ -1,13 +1,18 @@
 use super::Error;
+use super::Proxy;
 use super::Scheme;
+use super::Tls;
+use super::TlsSession;
 use super::Url;
-use rustls_platform_verifier::ConfigVerifierExt;
+use super::Version;
+use std::collections::HashMap;
 use std::iter::FusedIterator;
 use std::iter::from_fn;
+use std::net::IpAddr;
 use std::net::SocketAddr;
-use std::net::ToSocketAddrs;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::task::Context;
 use std::task::Poll;
 use std::time::Duration;
@@ -16,51 +21,190 @@ use tokio::io::AsyncRead;
 use tokio::io::AsyncWrite;
 use tokio::io::ReadBuf;
 use tokio::net::TcpStream;
+#[cfg(unix)]
+use tokio::net::UnixStream;
+use tokio::net::lookup_host;
+use tokio::sync::OnceCell;
 use tokio::task::JoinSet;
 use tokio::time;
 use tokio_rustls::TlsConnector;
 use tokio_rustls::client::TlsStream;
 use tokio_rustls::rustls::ClientConfig;
 
+/// How connections are opened: the HTTP version to offer, the proxy to go through,
+/// the TLS settings to use, addresses that replace DNS for `host:port` pairs and a
+/// Unix domain socket that replaces the network altogether.
+#[derive(Clone, Default)]
+pub struct Dialer {
+    pub version: Option<Version>,
+    pub proxy: Option<Proxy>,
+    pub tls: Tls,
+    pub resolve: HashMap<String, Vec<IpAddr>>,
+    pub socket: Option<String>,
+    /// The TLS configurations built so far, each once a connection first needed
+    /// it, shared by the clients cloned from one another
+    pub configs: Arc<Mutex<HashMap<Tls, Arc<OnceCell<ClientConfig>>>>>,
+}
+
+impl Dialer {
+    /// Whether requests to the url are handed to the proxy in absolute form instead
+    /// of going through a tunnel. Only plain HTTP/1.1 is forwarded.
+    /// A pinned target is tunnelled instead, so the proxy connects to its addresses.
+    pub fn forwards(&self, url: &Url) -> bool {
+        self.proxy.is_some()
+            && url.scheme == Scheme::Http
+            && self.version != Some(Version::Http20)
+            && self.addrs(&url.host, url.port).is_none()
+    }
+
+    /// Connections are shared per origin, requested version, proxy with its
+    /// credentials and TLS settings, so a connection opened one way is not handed to
+    /// a request that asked for another.
+    pub fn key(&self, url: &Url) -> String {
+        let mut key = format!("{}://{}:{}", url.scheme, url.host, url.port);
+        if let Some(version) = self.version {
+            key.push_str(&format!(" {version}"));
+        }
+        if let Some(proxy) = &self.proxy {
+            key.push_str(&format!(" via {}", proxy.url));
+            if let Some(authorization) = &proxy.authorization {
+                key.push_str(&format!(" as {authorization}"));
+            }
+        }
+        if url.scheme.secure() && self.tls != Tls::default() {
+            key.push_str(&format!(" {}", self.tls));
+        }
+        if let Some(socket) = &self.socket {
+            key.push_str(&format!(" unix {socket}"));
+        }
+        for host in std::iter::once(url).chain(self.proxy.as_ref().map(|proxy| &proxy.url)) {
+            if let Some(addrs) = self.addrs(&host.host, host.port) {
+                let addrs: Vec<String> = addrs.iter().map(IpAddr::to_string).collect();
+                key.push_str(&format!(
+                    " {}:{} at {}",
+                    host.host,
+                    host.port,
+                    addrs.join(",")
+                ));
+            }
+        }
+        key
+    }
+
+    /// The addresses that stand in for a DNS lookup of `host:port`, if any.
+    fn addrs(&self, host: &str, port: u16) -> Option<&Vec<IpAddr>> {
+        self.resolve
+            .get(&format!("{}:{port}", host.to_ascii_lowercase()))
+            .filter(|addrs| !addrs.is_empty())
+    }
+}
+
+const H2: &[u8] = b"h2";
+const HTTP11: &[u8] = b"http/1.1";
+
 pub enum Stream {
     Plain {
-        stream: TcpStream,
+        stream: Tcp,
         resolve: Duration,
     },
     Cipher {
-        stream: Box<TlsStream<TcpStream>>,
+        stream: Box<TlsStream<Tcp>>,
         resolve: Duration,
     },
+    #[cfg(unix)]
+    Unix(UnixStream),
     #[cfg(test)]
     Mock(std::io::Cursor<Vec<u8>>),
 }
 
 impl Stream {
-    pub async fn connect(url: &Url, tiomeout: Duration) -> Result<Self, Error> {
+    /// Connects to the url's origin, or to the dialer's proxy. Requests that cannot
+    /// be forwarded by the proxy get a `CONNECT` tunnel. Over TLS, ALPN offers the
+    /// dialer's version, or both h2 and http/1.1 when none is set. The timeout is
+    /// one deadline shared by the lookup, the connect and the tunnel.
+    pub async fn connect(url: &Url, tiomeout: Duration, dialer: &Dialer) -> Result<Self, Error> {
+        if let Some(socket) = &dialer.socket {
+            if url.scheme.secure() {
+                return Err(Error::InvalidSocket(format!(
+                    "{socket}: tls is not supported over unix sockets"
+                )));
+            }
+            return Self::connect_unix(socket, tiomeout).await;
+        }
         let host = url.host.as_str();
         let port = url.port;
-
-        match url.scheme {
-            Scheme::Http => {
-                let (stream, resolve) = Self::connect_tcp(host, port, tiomeout).await?;
-                Ok(Stream::Plain { stream, resolve })
+        let deadline = time::Instant::now() + tiomeout;
+        let (stream, resolve) = match &dialer.proxy {
+            Some(proxy) => {
+                let (stream, resolve) =
+                    Self::connect_tcp(&proxy.url.host, proxy.url.port, deadline, dialer).await?;
+                if dialer.forwards(url) {
+                    let stream = Tcp::new(stream, Vec::new());
+                    return Ok(Stream::Plain { stream, resolve });
+                }
+                // a pinned target is handed to the proxy by its first address
+                let target = match dialer.addrs(host, port).and_then(|addrs| addrs.first()) {
+                    Some(IpAddr::V6(addr)) => format!("[{addr}]"),
+                    Some(addr) => addr.to_string(),
+                    None => host.to_string(),
+                };
+                let tunnel = proxy.tunnel(stream, &target, port);
+                match time::timeout_at(deadline, tunnel).await {
+                    Ok(stream) => (stream?, resolve),
+                    Err(_) => return Err(Error::ConnectTimeout),
+                }
+            }
+            None => {
+                let (stream, resolve) = Self::connect_tcp(host, port, deadline, dialer).await?;
+                (Tcp::new(stream, Vec::new()), resolve)
             }
-            Scheme::Https => Self::connect_tls(host, port, tiomeout).await,
+        };
+        if url.scheme.secure() {
+            let stream = Self::connect_tls(stream, host, dialer).await?;
+            Ok(Stream::Cipher { stream, resolve })
+        } else {
+            Ok(Stream::Plain { stream, resolve })
+        }
+    }
+
+    #[cfg(unix)]
+    async fn connect_unix(path: &str, tiomeout: Duration) -> Result<Self, Error> {
+        match time::timeout(tiomeout, UnixStream::connect(path)).await {
+            Ok(Ok(stream)) => Ok(Stream::Unix(stream)),
+            Ok(Err(error)) => Err(Error::ConnectFailed(error)),
+            Err(_) => Err(Error::ConnectTimeout),
         }
     }
 
+    #[cfg(not(unix))]
+    async fn connect_unix(path: &str, _tiomeout: Duration) -> Result<Self, Error> {
+        Err(Error::InvalidSocket(format!(
+            "{path}: unix sockets are not supported on this platform"
+        )))
+    }
+
+    /// Resolves the host, unless the dialer pins its addresses, and connects to
+    /// whichever address answers first, both before the deadline.
     async fn connect_tcp(
         host: &str,
         port: u16,
-        tiomeout: Duration,
+        deadline: time::Instant,
+        dialer: &Dialer,
     ) -> Result<(TcpStream, Duration), Error> {
         let resolve = Instant::now();
-        let addrs = match (host, port).to_socket_addrs() {
-            Ok(addrs) => Ok(addrs.collect::<Vec<SocketAddr>>()),
-            Err(error) => Err(Error::HostNotFound(error)),
-        }?;
+        let addrs = match dialer.addrs(host, port) {
+            Some(addrs) => addrs
+                .iter()
+                .map(|addr| SocketAddr::new(*addr, port))
+                .collect(),
+            None => match time::timeout_at(deadline, lookup_host((host, port))).await {
+                Ok(Ok(addrs)) => addrs.collect::<Vec<SocketAddr>>(),
+                Ok(Err(error)) => return Err(Error::HostNotFound(error)),
+                Err(_) => return Err(Error::ConnectTimeout),
+            },
+        };
         let resolve = resolve.elapsed();
-        match time::timeout(tiomeout, Self::connect_happy(addrs)).await {
+        match time::timeout_at(deadline, Self::connect_happy(addrs)).await {
             Ok(Some(Ok(stream))) => Ok((stream, resolve)),
             Ok(Some(Err(error))) => Err(Error::ConnectFailed(error)),
             Ok(None) => Err(Error::NoConnectionAvailable),
@@ -68,21 +212,35 @@ impl Stream {
         }
     }
 
-    async fn connect_tls(host: &str, port: u16, tiomeout: Duration) -> Result<Self, Error> {
-        let config = ClientConfig::with_platform_verifier().map_err(|_e| Error::InvalidUrlHost)?;
+    async fn connect_tls(
+        stream: Tcp,
+        host: &str,
+        dialer: &Dialer,
+    ) -> Result<Box<TlsStream<Tcp>>, Error> {
+        let cell = dialer
+            .configs
+            .lock()
+            .unwrap()
+            .entry(dialer.tls.clone())
+            .or_default()
+            .clone();
+        let mut config = cell.get_or_try_init(|| dialer.tls.config()).await?.clone();
+        config.alpn_protocols = match dialer.version {
+            Some(Version::Http20) => vec![H2.to_vec()],
+            Some(_) => vec![HTTP11.to_vec()],
+            None => vec![H2.to_vec(), HTTP11.to_vec()],
+        };
         let connector = TlsConnector::from(Arc::new(config));
         let domain = host
             .to_owned()
             .try_into()
             .map_err(|_e| Error::InvalidUrlHost)?;
-        let (stream, resolve) = Self::connect_tcp(host, port, tiomeout).await?;
-        let stream = Box::new(
+        Ok(Box::new(
             connector
                 .connect(domain, stream)
                 .await
-                .map_err(|_e| Error::TlsHandshakeFailed)?,
-        );
-        Ok(Stream::Cipher { stream, resolve })
+                .map_err(Error::TlsHandshakeFailed)?,
+        ))
     }
 
     async fn connect_happy(addrs: Vec<SocketAddr>) -> Option<std::io::Result<TcpStream>> {
@@ -110,10 +268,28 @@ impl Stream {
         error
     }
 
+    /// Whether the TLS handshake negotiated HTTP/2 through ALPN.
+    pub fn negotiated_h2(&self) -> bool {
+        match self {
+            Stream::Cipher { stream, .. } => stream.get_ref().1.alpn_protocol() == Some(H2),
+            _ => false,
+        }
+    }
+
+    /// What the TLS handshake negotiated, for connections made over TLS.
+    pub fn tls_session(&self) -> Option<TlsSession> {
+        match self {
+            Stream::Cipher { stream, .. } => Some(TlsSession::from(stream.get_ref().1)),
+            _ => None,
+        }
+    }
+
     pub fn resolve(&self) -> Duration {
         match self {
             Stream::Plain { resolve, .. } => *resolve,
             Stream::Cipher { resolve, .. } => *resolve,
+            #[cfg(unix)]
+            Stream::Unix(_) => Duration::default(),
             #[cfg(test)]
             Stream::Mock(_) => Duration::default(),
         }
@@ -141,6 +317,8 @@ impl AsyncRead for Stream {
                 Poll::Ready(Err(error)) => Poll::Ready(Err(error)),
                 Poll::Pending => Poll::Pending,
             },
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
             #[cfg(test)]
             Stream::Mock(cursor) => Pin::new(cursor).poll_read(cx, buf),
         }
@@ -156,6 +334,8 @@ impl AsyncWrite for Stream {
         match self.get_mut() {
             Stream::Plain { stream, .. } => Pin::new(stream).poll_write(cx, buf),
             Stream::Cipher { stream, .. } => Pin::new(stream).poll_write(cx, buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
             #[cfg(test)]
             Stream::Mock(cursor) => Pin::new(cursor).poll_write(cx, buf),
         }
@@ -165,6 +345,8 @@ impl AsyncWrite for Stream {
         match self.get_mut() {
             Stream::Plain { stream, .. } => Pin::new(stream).poll_flush(cx),
             Stream::Cipher { stream, .. } => Pin::new(stream).poll_flush(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_flush(cx),
             #[cfg(test)]
             Stream::Mock(cursor) => Pin::new(cursor).poll_flush(cx),
         }
@@ -177,12 +359,65 @@ impl AsyncWrite for Stream {
         match self.get_mut() {
             Stream::Plain { stream, .. } => Pin::new(stream).poll_shutdown(cx),
             Stream::Cipher { stream, .. } => Pin::new(stream).poll_shutdown(cx),
+            #[cfg(unix)]
+            Stream::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
             #[cfg(test)]
             Stream::Mock(cursor) => Pin::new(cursor).poll_shutdown(cx),
         }
     }
 }
 
+/// A TCP connection that first replays any bytes already read from it, such as
+/// those a proxy sent along with its `CONNECT` response.
+pub struct Tcp {
+    stream: TcpStream,
+    buffered: Vec<u8>,
+}
+
+impl Tcp {
+    pub fn new(stream: TcpStream, buffered: Vec<u8>) -> Self {
+        Self { stream, buffered }
+    }
+}
+
+impl AsyncRead for Tcp {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let tcp = self.get_mut();
+        if tcp.buffered.is_empty() {
+            return Pin::new(&mut tcp.stream).poll_read(cx, buf);
+        }
+        let size = tcp.buffered.len().min(buf.remaining());
+        buf.put_slice(&tcp.buffered[..size]);
+        tcp.buffered.drain(..size);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for Tcp {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
+
 fn rotate<T, A: FusedIterator<Item = T>, B: FusedIterator<Item = T>>(
     mut a: A,
     mut b: B,
@@ -206,6 +441,7 @@ fn rotate<T, A: FusedIterator<Item = T>, B: FusedIterator<Item = T>>(
 
 #[cfg(test)]
 pub mod tests {
+    use super::Dialer;
     use super::Duration;
     use super::Stream;
     use super::Url;
@@ -243,6 +479,7 @@ pub mod tests {
         let stream = Stream::connect(
             &Url::from("http://127.0.0.1:30000/get"),
             Duration::from_secs(2),
+            &Dialer::default(),
         )
         .await;
         if let Err(error) = stream.as_ref() {
@@ -252,6 +489,7 @@ pub mod tests {
         let stream = Stream::connect(
             &Url::from("http://localhost:30000/get"),
             Duration::from_secs(2),
+            &Dialer::default(),
         )
         .await;
         if let Err(error) = stream.as_ref() {
@@ -261,6 +499,7 @@ pub mod tests {
         let stream = Stream::connect(
             &Url::from("http://localhost:88/get"),
             Duration::from_secs(2),
+            &Dialer::default(),
         )
         .await;
         assert!(stream.is_err());
@@ -0,0 +1,245 @@
+use super::Error;
+use rustls_platform_verifier::BuilderVerifierExt;
+use std::sync::Arc;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::rustls::ClientConnection;
+use tokio_rustls::rustls::DigitallySignedStruct;
+use tokio_rustls::rustls::ProtocolVersion;
+use tokio_rustls::rustls::RootCertStore;
+use tokio_rustls::rustls::SignatureScheme;
+use tokio_rustls::rustls::client::danger::HandshakeSignatureValid;
+use tokio_rustls::rustls::client::danger::ServerCertVerified;
+use tokio_rustls::rustls::client::danger::ServerCertVerifier;
+use tokio_rustls::rustls::crypto::CryptoProvider;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::pki_types::PrivateKeyDer;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::pki_types::UnixTime;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use x509_parser::extensions::GeneralName;
+
+/// Which servers to trust and which identity to present when connecting over TLS.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Tls {
+    /// A PEM file of CA certificates trusted instead of the platform's roots
+    pub ca: Option<String>,
+    /// A PEM file of the client certificate chain sent for mutual TLS
+    pub cert: Option<String>,
+    /// A PEM file of the private key for the client certificate
+    pub key: Option<String>,
+    /// Accept any server certificate without verification
+    pub insecure: bool,
+}
+
+impl Tls {
+    /// Builds the rustls configuration, reading the PEM files it names.
+    pub async fn config(&self) -> Result<ClientConfig, Error> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|error| Error::TlsConfig(error.to_string()))?;
+        let builder = if self.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(Insecure(provider)))
+        } else if let Some(ca) = &self.ca {
+            let mut roots = RootCertStore::empty();
+            for certificate in certificates(ca).await? {
+                roots
+                    .add(certificate)
+                    .map_err(|error| Error::TlsConfig(format!("{ca}: {error}")))?;
+            }
+            builder.with_root_certificates(roots)
+        } else {
+            builder
+                .with_platform_verifier()
+                .map_err(|error| Error::TlsConfig(error.to_string()))?
+        };
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(certificates(cert).await?, private_key(key).await?)
+                .map_err(|error| Error::TlsConfig(format!("{cert}: {error}"))),
+            (None, None) => Ok(builder.with_no_client_auth()),
+            _ => Err(Error::TlsConfig(
+                "client certificate and key must be set together".into(),
+            )),
+        }
+    }
+}
+
+/// What a TLS handshake negotiated, and the certificate chain the server presented.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TlsSession {
+    /// The protocol version, such as `TLSv1.3`
+    pub version: String,
+    /// The cipher suite, such as `TLS13_AES_256_GCM_SHA384`
+    pub cipher: String,
+    /// The ALPN protocol, empty when none was agreed
+    pub alpn: String,
+    /// The peer's certificates, starting with its own
+    pub certificates: Vec<Certificate>,
+}
+
+/// The identity and validity period of one certificate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    /// The subject alternative names: DNS names, IP addresses, emails and URIs
+    pub names: Vec<String>,
+    /// The start of the validity period in seconds since the unix epoch
+    pub not_before: i64,
+    /// The end of the validity period in seconds since the unix epoch
+    pub not_after: i64,
+}
+
+impl From<&ClientConnection> for TlsSession {
+    fn from(connection: &ClientConnection) -> Self {
+        let version = match connection.protocol_version() {
+            Some(ProtocolVersion::TLSv1_2) => String::from("TLSv1.2"),
+            Some(ProtocolVersion::TLSv1_3) => String::from("TLSv1.3"),
+            Some(version) => format!("{version:?}"),
+            None => String::default(),
+        };
+        let cipher = connection
+            .negotiated_cipher_suite()
+            .map(|suite| format!("{:?}", suite.suite()))
+            .unwrap_or_default();
+        let alpn = connection
+            .alpn_protocol()
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned())
+            .unwrap_or_default();
+        let certificates = connection
+            .peer_certificates()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|certificate| Certificate::parse(certificate))
+            .collect();
+        Self {
+            version,
+            cipher,
+            alpn,
+            certificates,
+        }
+    }
+}
+
+impl Certificate {
+    /// Reads the fields of a DER certificate, or `None` when it cannot be parsed.
+    fn parse(der: &CertificateDer<'_>) -> Option<Self> {
+        let (_, certificate) = x509_parser::parse_x509_certificate(der).ok()?;
+        let names = match certificate.subject_alternative_name() {
+            Ok(Some(extension)) => extension
+                .value
+                .general_names
+                .iter()
+                .filter_map(|name| match name {
+                    GeneralName::DNSName(name)
+                    | GeneralName::RFC822Name(name)
+                    | GeneralName::URI(name) => Some(name.to_string()),
+                    GeneralName::IPAddress(bytes) => match bytes.len() {
+                        4 => Some(std::net::IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?)),
+                        16 => Some(std::net::IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?)),
+                        _ => None,
+                    }
+                    .map(|address| address.to_string()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        Some(Self {
+            subject: certificate.subject().to_string(),
+            issuer: certificate.issuer().to_string(),
+            names,
+            not_before: certificate.validity().not_before.timestamp(),
+            not_after: certificate.validity().not_after.timestamp(),
+        })
+    }
+}
+
+impl std::fmt::Display for Tls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let path = |path: &Option<String>| path.as_deref().unwrap_or_default().to_string();
+        write!(
+            f,
+            "ca={} cert={} key={} insecure={}",
+            path(&self.ca),
+            path(&self.cert),
+            path(&self.key),
+            self.insecure
+        )
+    }
+}
+
+async fn read(path: &str) -> Result<Vec<u8>, Error> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|error| Error::TlsConfig(format!("{path}: {error}")))
+}
+
+async fn certificates(path: &str) -> Result<Vec<CertificateDer<'static>>, Error> {
+    let certificates = CertificateDer::pem_slice_iter(&read(path).await?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| Error::TlsConfig(format!("{path}: {error}")))?;
+    if certificates.is_empty() {
+        return Err(Error::TlsConfig(format!("{path}: no certificates found")));
+    }
+    Ok(certificates)
+}
+
+async fn private_key(path: &str) -> Result<PrivateKeyDer<'static>, Error> {
+    PrivateKeyDer::from_pem_slice(&read(path).await?)
+        .map_err(|error| Error::TlsConfig(format!("{path}: {error}")))
+}
+
+/// Accepts every server certificate while still checking handshake signatures, so
+/// the connection is encrypted but the peer is not authenticated.
+#[derive(Debug)]
+struct Insecure(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for Insecure {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+        tokio_rustls::rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
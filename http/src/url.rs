@@ -1,6 +1,6 @@
 use super::Scheme;
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Url {
     pub scheme: Scheme,
     pub host: String,
@@ -30,10 +30,8 @@ impl std::convert::From<&str> for Url {
         };
         let port = match port {
             Some(port) => port,
-            None => match scheme {
-                Scheme::Http => 80,
-                Scheme::Https => 443,
-            },
+            None if scheme.secure() => 443,
+            None => 80,
         };
         Url {
             scheme,
@@ -44,6 +42,79 @@ impl std::convert::From<&str> for Url {
     }
 }
 
+impl Url {
+    /// Resolves a reference such as a `Location` header value against this url,
+    /// removing `.` and `..` segments as RFC 3986 section 5.2 describes.
+    pub fn join(&self, reference: &str) -> String {
+        let base = self.to_string();
+        let base_path = self.path.split(['?', '#']).next().unwrap_or("/");
+        let (origin, target) = if let Some((scheme, rest)) = reference.split_once("://")
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        {
+            let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+            (
+                format!("{scheme}://{}", &rest[..end]),
+                rest[end..].to_string(),
+            )
+        } else if let Some(rest) = reference.strip_prefix("//") {
+            let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+            (
+                format!("{}://{}", self.scheme, &rest[..end]),
+                rest[end..].to_string(),
+            )
+        } else {
+            let origin = base[..base.len() - self.path.len()].to_string();
+            let target = if reference.starts_with('/') {
+                reference.to_string()
+            } else if reference.starts_with('?') {
+                format!("{base_path}{reference}")
+            } else if reference.is_empty() || reference.starts_with('#') {
+                let current = self.path.split('#').next().unwrap_or("/");
+                format!("{current}{reference}")
+            } else {
+                let directory = &base_path[..base_path.rfind('/').map_or(0, |i| i + 1)];
+                format!("{directory}{reference}")
+            };
+            (origin, target)
+        };
+        let end = target.find(['?', '#']).unwrap_or(target.len());
+        format!(
+            "{origin}{}{}",
+            remove_dot_segments(&target[..end]),
+            &target[end..]
+        )
+    }
+}
+
+/// Removes `.` and `..` segments from a path, keeping the trailing slash that a
+/// final dot segment leaves behind (RFC 3986 section 5.2.4).
+fn remove_dot_segments(path: &str) -> String {
+    let absolute = path.starts_with('/');
+    let mut segments = path.split('/').skip(usize::from(absolute)).peekable();
+    let mut output = Vec::new();
+    while let Some(segment) = segments.next() {
+        let last = segments.peek().is_none();
+        match segment {
+            "." => {}
+            ".." => {
+                output.pop();
+            }
+            segment => {
+                output.push(segment);
+                continue;
+            }
+        }
+        if last {
+            output.push("");
+        }
+    }
+    let path = output.join("/");
+    if absolute { format!("/{path}") } else { path }
+}
+
 impl std::convert::From<Option<&str>> for Url {
     fn from(str: Option<&str>) -> Self {
         match str {
@@ -67,7 +138,7 @@ impl Default for Url {
 impl std::fmt::Display for Url {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match (&self.scheme, &self.port) {
-            (Scheme::Http, 80) | (Scheme::Https, 443) => {
+            (Scheme::Http | Scheme::Ws, 80) | (Scheme::Https | Scheme::Wss, 443) => {
                 write!(f, "{}://{}{}", self.scheme, self.host, self.path)
             }
             _ => write!(
@@ -159,6 +230,37 @@ fn test_url_with_ip_port_path_query_fragment() {
     assert_eq!(url.path, "/hello/world?a=b#fragment");
 }
 
+#[test]
+fn test_url_join() {
+    let url = Url::from("http://IP_ADDRESS:52831/hello/world?a=b");
+    let tests = vec![
+        ("https://other/path", "https://other/path"),
+        ("//other:8080/path", "http://other:8080/path"),
+        ("/root?c=d", "http://IP_ADDRESS:52831/root?c=d"),
+        ("?c=d", "http://IP_ADDRESS:52831/hello/world?c=d"),
+        ("there", "http://IP_ADDRESS:52831/hello/there"),
+        ("./there", "http://IP_ADDRESS:52831/hello/there"),
+        ("../up/./x", "http://IP_ADDRESS:52831/up/x"),
+        ("../../../g?h=../i", "http://IP_ADDRESS:52831/g?h=../i"),
+        ("..", "http://IP_ADDRESS:52831/"),
+        ("#top", "http://IP_ADDRESS:52831/hello/world?a=b#top"),
+        ("", "http://IP_ADDRESS:52831/hello/world?a=b"),
+        ("/a/b/../c/.", "http://IP_ADDRESS:52831/a/c/"),
+        ("https://other/a/../b", "https://other/b"),
+        (
+            "/login?next=https://x",
+            "http://IP_ADDRESS:52831/login?next=https://x",
+        ),
+        (
+            "login?next=https://x/y",
+            "http://IP_ADDRESS:52831/hello/login?next=https://x/y",
+        ),
+    ];
+    for (reference, expected) in tests {
+        assert_eq!(url.join(reference), expected);
+    }
+}
+
 const TABLE: &[u8; 768] = b"\
       %00%01%02%03%04%05%06%07%08%09%0A%0B%0C%0D%0E%0F\
       %10%11%12%13%14%15%16%17%18%19%1A%1B%1C%1D%1E%1F\
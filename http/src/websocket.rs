@@ -0,0 +1,111 @@
+use super::Error;
+use super::Stream;
+use futures_util::SinkExt;
+use futures_util::StreamExt;
+use std::time::Duration;
+use tokio::time::timeout;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+
+/// A WebSocket opened by [`Client::open`](super::Client::open).
+pub struct WebSocket {
+    stream: WebSocketStream<Stream>,
+    /// The close frame the peer sent, once it has sent one
+    closed: Option<lib::Frame>,
+}
+
+impl WebSocket {
+    /// Takes over a connection whose opening handshake has completed. `buffered`
+    /// holds any frame bytes that were read along with the handshake response.
+    pub(crate) async fn new(stream: Stream, buffered: Vec<u8>) -> Self {
+        let stream =
+            WebSocketStream::from_partially_read(stream, buffered, Role::Client, None).await;
+        Self {
+            stream,
+            closed: None,
+        }
+    }
+
+    /// Sends a text, binary or close frame.
+    pub async fn send(&mut self, frame: lib::Frame) -> Result<(), Error> {
+        if self.closed.is_some() {
+            return Err(Error::WebSocketClosed);
+        }
+        let message = match frame {
+            lib::Frame::Text(text) => Message::text(text),
+            lib::Frame::Binary(bytes) => Message::binary(bytes),
+            lib::Frame::Close { code, reason } => Message::Close(code.map(|code| CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.into(),
+            })),
+        };
+        self.stream.send(message).await.map_err(Error::WebSocket)
+    }
+
+    /// Waits up to `tiomeout` for the next text, binary or close frame. Pings are
+    /// answered and skipped.
+    pub async fn receive(&mut self, tiomeout: Duration) -> Result<lib::Frame, Error> {
+        if self.closed.is_some() {
+            return Err(Error::WebSocketClosed);
+        }
+        timeout(tiomeout, self.next())
+            .await
+            .unwrap_or(Err(Error::ReadTimeout))
+    }
+
+    /// Sends a close frame, 1000 when no code is given, and waits up to `tiomeout`
+    /// for the peer's close frame, skipping any data that arrives first. When the
+    /// peer has already closed, its close frame is returned.
+    pub async fn close(
+        &mut self,
+        code: Option<u16>,
+        reason: String,
+        tiomeout: Duration,
+    ) -> Result<lib::Frame, Error> {
+        if let Some(frame) = &self.closed {
+            return Ok(frame.clone());
+        }
+        let frame = CloseFrame {
+            code: code.map_or(CloseCode::Normal, CloseCode::from),
+            reason: reason.into(),
+        };
+        self.stream
+            .close(Some(frame))
+            .await
+            .map_err(Error::WebSocket)?;
+        loop {
+            if let frame @ lib::Frame::Close { .. } = self.receive(tiomeout).await? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    async fn next(&mut self) -> Result<lib::Frame, Error> {
+        loop {
+            let message = match self.stream.next().await {
+                Some(message) => message.map_err(Error::WebSocket)?,
+                None => return Err(Error::WebSocketClosed),
+            };
+            let frame = match message {
+                Message::Text(text) => lib::Frame::Text(text.to_string()),
+                Message::Binary(bytes) => lib::Frame::Binary(bytes.to_vec()),
+                Message::Close(frame) => lib::Frame::Close {
+                    code: frame.as_ref().map(|frame| u16::from(frame.code)),
+                    reason: frame
+                        .map(|frame| frame.reason.to_string())
+                        .unwrap_or_default(),
+                },
+                Message::Ping(_) | Message::Pong(_) | Message::Frame(_) => continue,
+            };
+            if let lib::Frame::Close { .. } = frame {
+                // sends the close reply that the protocol queued
+                let _ = self.stream.flush().await;
+                self.closed = Some(frame.clone());
+            }
+            return Ok(frame);
+        }
+    }
+}
@@ -35,6 +35,15 @@ pub struct Api {
     pub host: Expr,
     pub port: Option<Expr>,
     pub headers: Vec<(Expr, Expr)>,
+    pub timeout: Vec<(Timeout, Expr)>,
+    pub follow: Option<Expr>,
+    pub downgrade: Option<Expr>,
+    pub version: Option<Expr>,
+    pub cookies: Option<Expr>,
+    pub proxy: Option<Expr>,
+    pub resolve: Option<Expr>,
+    pub tls: Vec<(String, Expr)>,
+    pub socket: Option<Expr>,
     pub requests: HashMap<String, Request>,
 }
 
@@ -52,6 +61,12 @@ pub struct Request {
     pub method: Method,
     pub params: Vec<(Expr, Expr)>,
     pub headers: Vec<(Expr, Expr)>,
+    pub timeout: Vec<(Timeout, Expr)>,
+    pub follow: Option<Expr>,
+    pub downgrade: Option<Expr>,
+    pub version: Option<Expr>,
+    pub upgrade: Upgrade,
+    pub events: Vec<(String, Expr)>,
     pub body: Body,
 }
 
@@ -101,7 +116,9 @@ impl From<&str> for $type {
     };
 }
 
-protocol_type!(Scheme, Http => "http", Https => "https");
+protocol_type!(Scheme, Http => "http", Https => "https", Ws => "ws", Wss => "wss");
+protocol_type!(Upgrade, None => "none", WebSocket => "websocket");
+protocol_type!(Timeout, Total => "total", Connect => "connect", Read => "read");
 protocol_type!(
     Method,
     Get => "GET",
@@ -126,6 +143,24 @@ impl Method {
     }
 }
 
+impl Upgrade {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "websocket" => Ok(Self::from(value)),
+            _ => Err(format!("unknown upgrade '{value}'")),
+        }
+    }
+}
+
+impl Timeout {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "connect" | "read" | "total" => Ok(Self::from(value)),
+            _ => Err(format!("unknown timeout '{value}'")),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::Method;
@@ -0,0 +1,379 @@
+use crate::Value;
+use std::collections::HashMap;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+/// Stores cookies from `Set-Cookie` headers and selects the ones a request
+/// should send, following the storage and retrieval rules of RFC 6265.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Jar {
+    cookies: Vec<Cookie>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+}
+
+/// The parts of a request url that cookie matching looks at.
+struct Target {
+    secure: bool,
+    host: String,
+    path: String,
+}
+
+impl Jar {
+    /// Stores one `Set-Cookie` value received from `url`. Values that are malformed or
+    /// name a domain the url does not belong to are ignored.
+    pub fn store(&mut self, url: &str, set_cookie: &str) {
+        let Some(target) = Target::parse(url) else {
+            return;
+        };
+        let mut attributes = set_cookie.split(';');
+        let Some((name, value)) = attributes.next().and_then(|pair| pair.split_once('=')) else {
+            return;
+        };
+        let name = name.trim();
+        if name.is_empty() {
+            return;
+        }
+        let now = SystemTime::now();
+        let mut cookie = Cookie {
+            name: name.to_string(),
+            value: value.trim().to_string(),
+            domain: target.host.clone(),
+            host_only: true,
+            path: default_path(&target.path),
+            expires: None,
+            secure: false,
+            http_only: false,
+        };
+        let mut max_age = None;
+        let mut expires = None;
+        for attribute in attributes {
+            let (key, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+            let value = value.trim();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "domain" => {
+                    let domain = value.trim_start_matches('.').to_ascii_lowercase();
+                    if domain.is_empty() {
+                        continue;
+                    }
+                    if !domain_match(&target.host, &domain) {
+                        return;
+                    }
+                    cookie.domain = domain;
+                    cookie.host_only = false;
+                }
+                "path" if value.starts_with('/') => cookie.path = value.to_string(),
+                "max-age" => {
+                    if let Ok(seconds) = value.parse::<i64>() {
+                        max_age = Some(match u64::try_from(seconds) {
+                            Ok(seconds) if seconds > 0 => {
+                                now.checked_add(Duration::from_secs(seconds))
+                            }
+                            _ => Some(UNIX_EPOCH),
+                        });
+                    }
+                }
+                "expires" => expires = parse_date(value).or(expires),
+                "secure" => cookie.secure = true,
+                "httponly" => cookie.http_only = true,
+                _ => {}
+            }
+        }
+        // Max-Age wins over Expires; an overflowing Max-Age means no expiry.
+        cookie.expires = max_age.unwrap_or(expires);
+        self.cookies.retain(|stored| {
+            !(stored.name == cookie.name
+                && stored.domain == cookie.domain
+                && stored.path == cookie.path)
+        });
+        if cookie.expires.is_none_or(|expires| expires > now) {
+            self.cookies.push(cookie);
+        }
+    }
+
+    /// Builds the `Cookie` header value for a request to `url`, longest paths first.
+    pub fn header(&mut self, url: &str) -> Option<String> {
+        let cookies = self.matching(url);
+        if cookies.is_empty() {
+            return None;
+        }
+        Some(
+            cookies
+                .iter()
+                .map(|cookie| format!("{}={}", cookie.name, cookie.value))
+                .collect::<Vec<_>>()
+                .join("; "),
+        )
+    }
+
+    /// Lists the stored cookies, or only those sent to `url`, as script values.
+    pub fn values(&mut self, url: Option<&str>) -> Value {
+        let cookies = match url {
+            Some(url) => self.matching(url),
+            None => {
+                self.expire();
+                self.cookies.iter().collect()
+            }
+        };
+        Value::Array(
+            cookies
+                .into_iter()
+                .map(|cookie| {
+                    Value::Map(HashMap::from([
+                        ("name".into(), Value::String(cookie.name.clone())),
+                        ("value".into(), Value::String(cookie.value.clone())),
+                        ("domain".into(), Value::String(cookie.domain.clone())),
+                        ("path".into(), Value::String(cookie.path.clone())),
+                        ("secure".into(), Value::Boolean(cookie.secure)),
+                        ("httponly".into(), Value::Boolean(cookie.http_only)),
+                        (
+                            "expires".into(),
+                            cookie
+                                .expires
+                                .and_then(|expires| expires.duration_since(UNIX_EPOCH).ok())
+                                .map_or(Value::Null, |expires| {
+                                    Value::Integer(expires.as_secs() as i64)
+                                }),
+                        ),
+                    ]))
+                })
+                .collect(),
+        )
+    }
+
+    fn matching(&mut self, url: &str) -> Vec<&Cookie> {
+        self.expire();
+        let Some(target) = Target::parse(url) else {
+            return Vec::new();
+        };
+        let mut cookies: Vec<&Cookie> = self
+            .cookies
+            .iter()
+            .filter(|cookie| {
+                let domain = if cookie.host_only {
+                    target.host == cookie.domain
+                } else {
+                    domain_match(&target.host, &cookie.domain)
+                };
+                domain
+                    && path_match(&target.path, &cookie.path)
+                    && (target.secure || !cookie.secure)
+            })
+            .collect();
+        // stable sort keeps earlier stored cookies first among equal paths
+        cookies.sort_by_key(|cookie| std::cmp::Reverse(cookie.path.len()));
+        cookies
+    }
+
+    fn expire(&mut self) {
+        let now = SystemTime::now();
+        self.cookies
+            .retain(|cookie| cookie.expires.is_none_or(|expires| expires > now));
+    }
+}
+
+impl Target {
+    fn parse(url: &str) -> Option<Self> {
+        let (scheme, rest) = url.split_once("://")?;
+        let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        let authority = &rest[..end];
+        let authority = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let host = match authority.rsplit_once(':') {
+            Some((host, port)) if port.bytes().all(|byte| byte.is_ascii_digit()) => host,
+            _ => authority,
+        };
+        let path = rest[end..].split(['?', '#']).next().unwrap_or_default();
+        Some(Self {
+            secure: scheme.eq_ignore_ascii_case("https") || scheme.eq_ignore_ascii_case("wss"),
+            host: host.to_ascii_lowercase(),
+            path: if path.is_empty() { "/" } else { path }.to_string(),
+        })
+    }
+}
+
+/// The directory of the request path, used when a cookie has no Path attribute.
+fn default_path(path: &str) -> String {
+    match path.rfind('/') {
+        Some(0) | None => "/".to_string(),
+        Some(index) => path[..index].to_string(),
+    }
+}
+
+fn domain_match(host: &str, domain: &str) -> bool {
+    host == domain
+        || host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<std::net::IpAddr>().is_err()
+}
+
+fn path_match(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/'))
+}
+
+/// Parses a cookie date with the lenient algorithm of RFC 6265 section 5.1.1,
+/// which accepts the common HTTP date formats.
+fn parse_date(text: &str) -> Option<SystemTime> {
+    let mut time = None;
+    let mut day = None;
+    let mut month = None;
+    let mut year = None;
+    for token in text
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == ':'))
+        .filter(|token| !token.is_empty())
+    {
+        let digits = token.bytes().take_while(u8::is_ascii_digit).count();
+        if time.is_none()
+            && let Some(parts) = token
+                .split(':')
+                .map(|part| part.parse().ok())
+                .collect::<Option<Vec<u64>>>()
+            && let [hour, minute, second] = parts[..]
+        {
+            time = Some((hour, minute, second));
+            continue;
+        }
+        if day.is_none() && (1..=2).contains(&digits) && token.len() == digits {
+            day = token.parse::<u64>().ok();
+            continue;
+        }
+        if month.is_none()
+            && token.len() >= 3
+            && let Some(index) = [
+                "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+            ]
+            .iter()
+            .position(|name| token[..3].eq_ignore_ascii_case(name))
+        {
+            month = Some(index as u64 + 1);
+            continue;
+        }
+        if year.is_none() && (2..=4).contains(&digits) && token.len() == digits && day.is_some() {
+            year = token.parse::<u64>().ok();
+        }
+    }
+    let (hour, minute, second) = time?;
+    let (day, month) = (day?, month?);
+    let year = match year? {
+        year @ 70..=99 => year + 1900,
+        year @ 0..=69 => year + 2000,
+        year => year,
+    };
+    if !(1..=31).contains(&day) || year < 1601 || hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    // days from the civil date, after Howard Hinnant's algorithm
+    let (year, month) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = year / 400;
+    let day_of_era = (year - era * 400) * 365 + (year - era * 400) / 4 - (year - era * 400) / 100
+        + (153 * month + 2) / 5
+        + day
+        - 1;
+    let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(
+        days * 86400 + hour * 3600 + minute * 60 + second,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_domain_path_and_secure_attributes() {
+        let mut jar = Jar::default();
+        jar.store(
+            "http://api.example.test/login",
+            "session=abc; Path=/; HttpOnly",
+        );
+        jar.store(
+            "http://api.example.test/login",
+            "shared=1; Domain=.example.test",
+        );
+        jar.store("http://api.example.test/users/1", "scoped=2");
+        jar.store("https://api.example.test/", "token=3; Secure");
+        jar.store("http://api.example.test/", "stolen=4; Domain=other.test");
+
+        assert_eq!(
+            jar.header("http://api.example.test/users/2").as_deref(),
+            Some("scoped=2; session=abc; shared=1")
+        );
+        assert_eq!(
+            jar.header("https://api.example.test:8443/").as_deref(),
+            Some("session=abc; shared=1; token=3")
+        );
+        assert_eq!(
+            jar.header("http://www.example.test/").as_deref(),
+            Some("shared=1")
+        );
+        assert_eq!(jar.header("http://other.test/"), None);
+        assert_eq!(
+            jar.header("http://api.example.test/usersx"),
+            Some("session=abc; shared=1".into())
+        );
+    }
+
+    #[test]
+    fn replaces_and_expires_cookies() {
+        let mut jar = Jar::default();
+        jar.store("http://example.test/", "a=1");
+        jar.store("http://example.test/", "a=2");
+        jar.store(
+            "http://example.test/",
+            "b=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+        );
+        jar.store(
+            "http://example.test/",
+            "c=1; Expires=Fri, 01 Jan 2100 00:00:00 GMT",
+        );
+        jar.store(
+            "http://example.test/",
+            "d=1; Max-Age=60; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+        );
+        assert_eq!(
+            jar.header("http://example.test/").as_deref(),
+            Some("a=2; c=1; d=1")
+        );
+
+        jar.store("http://example.test/", "c=1; Max-Age=0");
+        assert_eq!(
+            jar.header("http://example.test/").as_deref(),
+            Some("a=2; d=1")
+        );
+        let Value::Array(cookies) = jar.values(None) else {
+            panic!("cookies are not an array");
+        };
+        assert_eq!(cookies.len(), 2);
+    }
+
+    #[test]
+    fn parses_cookie_dates() {
+        let expected = UNIX_EPOCH + Duration::from_secs(1445412480);
+        for text in [
+            "Wed, 21 Oct 2015 07:28:00 GMT",
+            "Wednesday, 21-Oct-15 07:28:00 GMT",
+            "Wed Oct 21 07:28:00 2015",
+        ] {
+            assert_eq!(parse_date(text), Some(expected), "{text}");
+        }
+        assert_eq!(parse_date("yesterday"), None);
+    }
+}
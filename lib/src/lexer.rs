@@ -1,9 +1,11 @@
 use std::char;
 
+use super::File;
 use super::Kind;
 use super::Span;
 use super::Token;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct Lexer;
 
@@ -14,14 +16,19 @@ impl Lexer {
 
     #[cfg(test)]
     pub fn segment(&mut self, text: &str) -> Vec<Token> {
-        self.segment_with_string_ends(text, &HashMap::new())
+        let file = Arc::new(File {
+            name: String::new(),
+            text: text.to_owned(),
+        });
+        self.segment_with_string_ends(&file, &HashMap::new())
     }
 
     pub(crate) fn segment_with_string_ends(
         &mut self,
-        text: &str,
+        file: &Arc<File>,
         string_ends: &HashMap<usize, usize>,
     ) -> Vec<Token> {
+        let text = file.text.as_str();
         let bytes = text.as_bytes();
         let mut tokens = Vec::new();
         let mut index = 0;
@@ -83,6 +90,9 @@ impl Lexer {
                 b',' => (Kind::Comma, ",".to_owned(), index),
                 b';' => (Kind::Semi, ";".to_owned(), index),
                 b':' => (Kind::Colon, ":".to_owned(), index),
+                b'.' if bytes.get(index + 1) == Some(&b'.') => {
+                    (Kind::Range, "..".to_owned(), index + 1)
+                }
                 b'.' => (Kind::Dot, ".".to_owned(), index),
                 b'(' => (Kind::Lp, "(".to_owned(), index),
                 b')' => (Kind::Rp, ")".to_owned(), index),
@@ -122,6 +132,16 @@ impl Lexer {
                         "let" => Kind::Let,
                         "test" => Kind::Test,
                         "expect" => Kind::Expect,
+                        "if" => Kind::If,
+                        "else" => Kind::Else,
+                        "for" => Kind::For,
+                        "in" => Kind::In,
+                        "break" => Kind::Break,
+                        "continue" => Kind::Continue,
+                        "fn" => Kind::Fn,
+                        "return" => Kind::Return,
+                        "import" => Kind::Import,
+                        "as" => Kind::As,
                         "true" => Kind::True,
                         "false" => Kind::False,
                         "null" => Kind::Null,
@@ -205,7 +225,12 @@ impl Lexer {
                     None => (Kind::Illegal, String::new(), index),
                 },
             };
-            tokens.push(Token::new(kind, Span { start, end }, lite));
+            let span = Span {
+                file: file.clone(),
+                start,
+                end,
+            };
+            tokens.push(Token::new(kind, span, lite));
             index = end + 1;
         }
         tokens
@@ -258,13 +283,25 @@ mod tests {
     #[test]
     fn lexer_segments_literals_and_keywords() {
         assert_eq!(
-            lex("env api let test expect true false null name 42 3.14 \"text\" `raw`"),
+            lex(
+                "env api let test expect if else for in break continue fn return import as true false null name 42 3.14 \"text\" `raw`"
+            ),
             owned(vec![
                 (Kind::Env, "env"),
                 (Kind::Api, "api"),
                 (Kind::Let, "let"),
                 (Kind::Test, "test"),
                 (Kind::Expect, "expect"),
+                (Kind::If, "if"),
+                (Kind::Else, "else"),
+                (Kind::For, "for"),
+                (Kind::In, "in"),
+                (Kind::Break, "break"),
+                (Kind::Continue, "continue"),
+                (Kind::Fn, "fn"),
+                (Kind::Return, "return"),
+                (Kind::Import, "import"),
+                (Kind::As, "as"),
                 (Kind::True, "true"),
                 (Kind::False, "false"),
                 (Kind::Null, "null"),
@@ -280,13 +317,14 @@ mod tests {
     #[test]
     fn lexer_segments_delimiters_and_operators() {
         assert_eq!(
-            lex("= , ; : . @ ( ) { } [ ] + - * / % ! ^ | & << >> || && < > <= >= == !="),
+            lex("= , ; : . .. @ ( ) { } [ ] + - * / % ! ^ | & << >> || && < > <= >= == !="),
             owned(vec![
                 (Kind::Assign, "="),
                 (Kind::Comma, ","),
                 (Kind::Semi, ";"),
                 (Kind::Colon, ":"),
                 (Kind::Dot, "."),
+                (Kind::Range, ".."),
                 (Kind::Tag, "@"),
                 (Kind::Lp, "("),
                 (Kind::Rp, ")"),
@@ -376,11 +414,8 @@ mod tests {
             assert_eq!(tokens.len(), 1);
             assert_eq!(tokens[0].kind, Kind::Illegal);
             assert_eq!(
-                tokens[0].span,
-                Span {
-                    start: 0,
-                    end: text.len() - 1
-                }
+                (tokens[0].span.start, tokens[0].span.end),
+                (0, text.len() - 1)
             );
         }
     }
@@ -388,12 +423,17 @@ mod tests {
     #[test]
     fn lexer_tracks_utf8_byte_spans_and_illegal_characters() {
         let tokens = Lexer::new().segment("name 🍀 next");
-        assert_eq!(tokens[0].span, Span { start: 0, end: 3 });
+        let span = |start, end| Span {
+            file: tokens[0].span.file.clone(),
+            start,
+            end,
+        };
+        assert_eq!(tokens[0].span, span(0, 3));
         assert_eq!(
             tokens[1],
-            Token::new(Kind::Illegal, Span { start: 5, end: 8 }, "🍀".into())
+            Token::new(Kind::Illegal, span(5, 8), "🍀".into())
         );
-        assert_eq!(tokens[2].span, Span { start: 10, end: 13 });
+        assert_eq!(tokens[2].span, span(10, 13));
     }
 
     #[test]
@@ -5,6 +5,7 @@ macro_rules! string {
 }
 
 pub mod api;
+mod cookie;
 mod lexer;
 pub mod mech;
 mod native;
@@ -15,21 +16,33 @@ mod token;
 mod trans;
 mod value;
 
-pub use syntax::Expr;
+pub use cookie::Jar;
+pub use syntax::{Expr, ExprKind};
+pub use token::File;
 use token::Kind;
-use token::Span;
+pub use token::Span;
 use token::Token;
 pub use value::Value;
 
 pub use mech::{Mech, Report, Trans};
-pub use native::{Function, Output, Registry};
+pub use native::{Function, Output, Registry, digest};
 pub use parser::Parser;
 pub use stat::Stats;
 pub use syntax::Source;
+pub use trans::Certificate;
 pub use trans::Content;
+pub use trans::Error;
+pub use trans::ErrorKind;
+pub use trans::Event;
+pub use trans::Events;
+pub use trans::Frame;
 pub use trans::Header;
+pub use trans::Part;
 pub use trans::Pending;
 pub use trans::Request;
 pub use trans::Response;
 pub use trans::Result;
+pub use trans::Timeout;
 pub use trans::Timing;
+pub use trans::Tls;
+pub use trans::TlsSession;
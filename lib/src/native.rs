@@ -1,7 +1,9 @@
 use crate::Value;
+use crate::cookie::Jar;
 use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-pub type Function = fn(Vec<Value>) -> Result<Output, String>;
+pub type Function = Arc<dyn Fn(Vec<Value>) -> Result<Output, String> + Send + Sync>;
 
 pub struct Output {
     pub value: Value,
@@ -20,13 +22,37 @@ impl Default for Registry {
         };
         registry.register("secret", secret);
         registry.register("file", file);
+        registry.register("sha256", sha256);
+        registry.register("save", save);
         registry
     }
 }
 
 impl Registry {
-    pub fn register(&mut self, name: impl Into<String>, function: Function) {
-        self.functions.insert(name.into(), function);
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        function: impl Fn(Vec<Value>) -> Result<Output, String> + Send + Sync + 'static,
+    ) {
+        self.functions.insert(name.into(), Arc::new(function));
+    }
+
+    /// Registers `cookies`, which lists the cookies in `jar`, or those it would
+    /// send to a url.
+    pub(crate) fn with_cookies(mut self, jar: Arc<Mutex<Jar>>) -> Self {
+        self.register("cookies", move |arguments: Vec<Value>| {
+            let mut jar = jar.lock().unwrap();
+            let value = match &arguments[..] {
+                [] => jar.values(None),
+                [Value::String(url)] => jar.values(Some(url)),
+                _ => return Err("cookies requires no argument or a url string".into()),
+            };
+            Ok(Output {
+                value,
+                sensitive: false,
+            })
+        });
+        self
     }
 
     pub fn call(&self, name: &str, arguments: Vec<Value>) -> Option<Result<Output, String>> {
@@ -51,6 +77,48 @@ fn file(arguments: Vec<Value>) -> Result<Output, String> {
     })
 }
 
+/// The hex SHA-256 of some bytes, as `sha256` returns it.
+pub fn digest(bytes: impl AsRef<[u8]>) -> String {
+    use sha2::Digest;
+    let digest = sha2::Sha256::digest(bytes);
+    digest.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn sha256(arguments: Vec<Value>) -> Result<Output, String> {
+    if arguments.len() != 1 {
+        return Err("sha256 requires one argument".into());
+    }
+    let digest = match (&arguments[0], file_path(&arguments[0])) {
+        (_, Some(path)) => {
+            let bytes = std::fs::read(path).map_err(|error| format!("{path}: {error}"))?;
+            digest(bytes)
+        }
+        (Value::Bytes(bytes), None) => digest(bytes),
+        (Value::String(value), None) => digest(value),
+        (value, None) => return Err(format!("sha256 requires bytes or a string, got {value:?}")),
+    };
+    Ok(Output {
+        value: Value::String(digest),
+        sensitive: false,
+    })
+}
+
+fn save(arguments: Vec<Value>) -> Result<Output, String> {
+    let [value, Value::String(path)] = &arguments[..] else {
+        return Err("save requires a value and a string path".into());
+    };
+    let bytes = match value {
+        Value::Bytes(bytes) => bytes.as_slice(),
+        Value::String(text) if file_path(value).is_none() => text.as_bytes(),
+        value => return Err(format!("save requires bytes or a string, got {value:?}")),
+    };
+    std::fs::write(path, bytes).map_err(|error| format!("{path}: {error}"))?;
+    Ok(Output {
+        value: Value::Integer(bytes.len() as i64),
+        sensitive: false,
+    })
+}
+
 fn one_string(name: &str, arguments: Vec<Value>) -> Result<String, String> {
     if arguments.len() != 1 {
         return Err(format!("{name} requires one argument"));
@@ -91,4 +159,31 @@ mod tests {
             .unwrap();
         assert_eq!(output.value, Value::String("FAN".into()));
     }
+
+    #[test]
+    fn hashes_and_saves_bytes() {
+        let path = std::env::temp_dir().join(format!("basjoofan-{}-save", std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        let bytes = Value::Bytes(vec![0, 159, 146, 150, 255]);
+        let saved = save(vec![bytes.clone(), Value::String(path.clone())]).unwrap();
+        assert_eq!(saved.value, Value::Integer(5));
+        let digest = Value::String(
+            "e92ad0d01485ac7095ffc21874b70b74f9667cf2c937b8ab2527a96d847fb21e".into(),
+        );
+        assert_eq!(sha256(vec![bytes]).unwrap().value, digest);
+        let reference = file(vec![Value::String(path.clone())]).unwrap().value;
+        assert_eq!(sha256(vec![reference.clone()]).unwrap().value, digest);
+        std::fs::remove_file(&path).unwrap();
+        let Err(error) = sha256(vec![reference]) else {
+            panic!("hashing a missing file succeeded");
+        };
+        assert!(error.starts_with(&path), "{error}");
+        assert_eq!(
+            sha256(vec![Value::String("abc".into())]).unwrap().value,
+            Value::String(
+                "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".into()
+            )
+        );
+        assert!(save(vec![Value::Integer(1), Value::String("x".into())]).is_err());
+    }
 }
@@ -1,24 +1,68 @@
 use super::lexer::Lexer;
-use super::{Expr, Kind, Source, Token};
-use crate::api::{Api, Body, Method, Request};
-use crate::syntax::{Declaration, Environment, Test};
+use super::{Expr, ExprKind, File, Kind, Source, Span, Token};
+use crate::api::{Api, Body, Method, Parameter, Request, Timeout, Upgrade};
+use crate::syntax::{Declaration, Environment, Function, Import, Test};
+use std::cell::Cell;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub struct Parser {
+    file: Arc<File>,
     tokens: Vec<Token>,
     index: usize,
+    /// The token an error was reported at, when it is not the next one
+    failure: Cell<Option<usize>>,
+    /// How many loops enclose the statement being parsed
+    loops: usize,
+    /// Whether the statement being parsed is inside a function
+    function: bool,
 }
 
 impl Parser {
     pub fn new(text: &str) -> Self {
+        Self::named("<input>", text)
+    }
+
+    /// Creates a parser for a file, whose name locates errors and expressions.
+    pub fn named(name: &str, text: &str) -> Self {
+        let file = Arc::new(File {
+            name: name.to_owned(),
+            text: text.to_owned(),
+        });
         let string_ends = string_ends(text);
         Self {
-            tokens: Lexer::new().segment_with_string_ends(text, &string_ends),
+            tokens: Lexer::new().segment_with_string_ends(&file, &string_ends),
+            file,
             index: 0,
+            failure: Cell::new(None),
+            loops: 0,
+            function: false,
         }
     }
 
+    /// Parses the whole text. An error names the file, line and column it was
+    /// found at and quotes the line.
     pub fn parse(&mut self) -> Result<Source, String> {
+        self.parse_source().map_err(|message| {
+            let index = self.failure.get().unwrap_or(self.index);
+            let span = match self.tokens.get(index) {
+                Some(token) => token.span.clone(),
+                None => self.end(),
+            };
+            span.render(&message)
+        })
+    }
+
+    /// Parses the expression of a string interpolation. Errors are left without a
+    /// location, to be reported at the string.
+    pub(crate) fn interpolation(&mut self) -> Result<Expr, String> {
+        if self.peek().is_none() {
+            return Err("empty interpolation".into());
+        }
+        self.parse_expr()
+    }
+
+    fn parse_source(&mut self) -> Result<Source, String> {
         let mut source = Source::new();
         while self.peek().is_some() {
             let tags = self.parse_tags()?;
@@ -45,7 +89,29 @@ impl Parser {
                     }
                     let api = self.parse_api()?;
                     source.apis.insert(api.clone())?;
-                    source.declarations.push(Declaration::Api(api));
+                    source.declarations.push(Declaration::Api(Box::new(api)));
+                }
+                Some(Kind::Fn) => {
+                    if !tags.is_empty() {
+                        return Err(self.error("annotations only apply to tests"));
+                    }
+                    let function = self.parse_function()?;
+                    if source
+                        .functions
+                        .insert(function.name.clone(), function.clone())
+                        .is_some()
+                    {
+                        return Err(self.error("duplicate function"));
+                    }
+                    source.declarations.push(Declaration::Function(function));
+                }
+                Some(Kind::Import) => {
+                    if !tags.is_empty() {
+                        return Err(self.error("annotations only apply to tests"));
+                    }
+                    let import = self.parse_import()?;
+                    source.imports.push(import.clone());
+                    source.declarations.push(Declaration::Import(import));
                 }
                 Some(Kind::Test) => {
                     let test = self.parse_test(tags)?;
@@ -82,6 +148,15 @@ impl Parser {
         let mut host = None;
         let mut port = None;
         let mut headers = Vec::new();
+        let mut timeout = Vec::new();
+        let mut follow = None;
+        let mut downgrade = None;
+        let mut version = None;
+        let mut cookies = None;
+        let mut proxy = None;
+        let mut resolve = None;
+        let mut tls = Vec::new();
+        let mut socket = None;
         let mut requests = HashMap::new();
         let mut api_fields = std::collections::HashSet::new();
 
@@ -102,6 +177,15 @@ impl Parser {
                     "host" => host = Some(self.parse_expr()?),
                     "port" => port = Some(self.parse_expr()?),
                     "headers" => headers = self.parse_pairs()?,
+                    "timeout" => timeout = self.parse_timeout()?,
+                    "follow" => follow = Some(self.parse_expr()?),
+                    "downgrade" => downgrade = Some(self.parse_expr()?),
+                    "version" => version = Some(self.parse_expr()?),
+                    "cookies" => cookies = Some(self.parse_expr()?),
+                    "proxy" => proxy = Some(self.parse_expr()?),
+                    "resolve" => resolve = Some(self.parse_expr()?),
+                    "tls" => tls = self.parse_tls()?,
+                    "socket" => socket = Some(self.parse_expr()?),
                     _ => return Err(self.error(format!("unknown api field '{field}'"))),
                 }
             }
@@ -116,50 +200,32 @@ impl Parser {
             host: host.ok_or_else(|| self.error("api requires host"))?,
             port,
             headers,
+            timeout,
+            follow,
+            downgrade,
+            version,
+            cookies,
+            proxy,
+            resolve,
+            tls,
+            socket,
             requests,
         })
     }
 
     fn parse_request(&mut self, name: String) -> Result<Request, String> {
-        let mut params_def = Vec::new();
-        while !self.consume(Kind::Rp) {
-            let parameter = self.ident()?;
-            self.expect(Kind::Colon)?;
-            let kind = self.ident()?;
-            if !matches!(
-                kind.as_str(),
-                "int"
-                    | "integer"
-                    | "float"
-                    | "string"
-                    | "bool"
-                    | "boolean"
-                    | "array"
-                    | "map"
-                    | "file"
-            ) {
-                return Err(self.error(format!("unknown parameter type '{kind}'")));
-            }
-            if params_def
-                .iter()
-                .any(|value: &crate::api::Parameter| value.name == parameter)
-            {
-                return Err(self.error(format!("duplicate parameter '{parameter}'")));
-            }
-            params_def.push(crate::api::Parameter {
-                name: parameter,
-                kind,
-            });
-            if !self.consume(Kind::Comma) {
-                self.expect(Kind::Rp)?;
-                break;
-            }
-        }
+        let params_def = self.parse_parameters()?;
         self.expect(Kind::Lb)?;
         let mut method = None;
         let mut path = None;
         let mut params = Vec::new();
         let mut headers = Vec::new();
+        let mut timeout = Vec::new();
+        let mut follow = None;
+        let mut downgrade = None;
+        let mut version = None;
+        let mut upgrade = Upgrade::None;
+        let mut events = Vec::new();
         let mut body = Body::None;
         let mut request_fields = std::collections::HashSet::new();
         while !self.consume(Kind::Rb) {
@@ -180,6 +246,12 @@ impl Parser {
                 "path" => path = Some(self.parse_expr()?),
                 "params" => params = self.parse_pairs()?,
                 "headers" => headers = self.parse_pairs()?,
+                "timeout" => timeout = self.parse_timeout()?,
+                "follow" => follow = Some(self.parse_expr()?),
+                "downgrade" => downgrade = Some(self.parse_expr()?),
+                "version" => version = Some(self.parse_expr()?),
+                "upgrade" => upgrade = Upgrade::parse(&self.ident()?)?,
+                "events" => events = self.parse_events()?,
                 "json" => body = Body::Json(self.parse_expr()?),
                 "form" => body = Body::Form(self.parse_pairs()?),
                 "multipart" => body = Body::Part(self.parse_pairs()?),
@@ -199,22 +271,192 @@ impl Parser {
                 .ok_or_else(|| self.error(format!("request '{name}' requires method")))?,
             params,
             headers,
+            timeout,
+            follow,
+            downgrade,
+            version,
+            upgrade,
+            events,
             body,
         })
     }
 
+    fn parse_timeout(&mut self) -> Result<Vec<(Timeout, Expr)>, String> {
+        let mut timeout = Vec::new();
+        for (key, value) in self.parse_fields()? {
+            let key = Timeout::parse(&key).map_err(|error| self.error(error))?;
+            timeout.push((key, value));
+        }
+        Ok(timeout)
+    }
+
+    fn parse_events(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        self.parse_options("events", &["count", "until", "timeout"])
+    }
+
+    fn parse_tls(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        self.parse_options("tls", &["ca", "cert", "key", "insecure"])
+    }
+
+    fn parse_options(
+        &mut self,
+        option: &str,
+        names: &[&str],
+    ) -> Result<Vec<(String, Expr)>, String> {
+        let fields = self.parse_fields()?;
+        if let Some((key, _)) = fields
+            .iter()
+            .find(|(key, _)| !names.contains(&key.as_str()))
+        {
+            return Err(self.error(format!("unknown {option} '{key}'")));
+        }
+        Ok(fields)
+    }
+
+    /// Parses typed parameters up to and including the closing parenthesis.
+    fn parse_parameters(&mut self) -> Result<Vec<Parameter>, String> {
+        let mut params_def = Vec::new();
+        while !self.consume(Kind::Rp) {
+            let parameter = self.ident()?;
+            self.expect(Kind::Colon)?;
+            let kind = self.ident()?;
+            if !matches!(
+                kind.as_str(),
+                "int"
+                    | "integer"
+                    | "float"
+                    | "string"
+                    | "bytes"
+                    | "bool"
+                    | "boolean"
+                    | "array"
+                    | "map"
+                    | "file"
+            ) {
+                return Err(self.error(format!("unknown parameter type '{kind}'")));
+            }
+            if params_def
+                .iter()
+                .any(|value: &Parameter| value.name == parameter)
+            {
+                return Err(self.error(format!("duplicate parameter '{parameter}'")));
+            }
+            params_def.push(Parameter {
+                name: parameter,
+                kind,
+            });
+            if !self.consume(Kind::Comma) {
+                self.expect(Kind::Rp)?;
+                break;
+            }
+        }
+        Ok(params_def)
+    }
+
+    fn parse_function(&mut self) -> Result<Function, String> {
+        self.expect(Kind::Fn)?;
+        let name = self.ident()?;
+        self.expect(Kind::Lp)?;
+        let params = self.parse_parameters()?;
+        self.function = true;
+        let body = self.parse_block();
+        self.function = false;
+        Ok(Function {
+            name,
+            params,
+            body: body?,
+        })
+    }
+
+    /// Parses `import "path"` with an optional `as alias`.
+    fn parse_import(&mut self) -> Result<Import, String> {
+        self.expect(Kind::Import)?;
+        let path = match self.peek() {
+            Some(Token {
+                kind: Kind::String,
+                lite,
+                ..
+            }) => lite.clone(),
+            Some(token) => return Err(self.error(format!("expected import path, found {token}"))),
+            None => return Err("expected import path, found end of input".to_string()),
+        };
+        self.index += 1;
+        let alias = match self.consume(Kind::As) {
+            true => Some(self.ident()?),
+            false => None,
+        };
+        Ok(Import { path, alias })
+    }
+
     fn parse_test(&mut self, tags: Vec<String>) -> Result<Test, String> {
         self.expect(Kind::Test)?;
         let name = self.ident()?;
         self.expect(Kind::Lb)?;
         let mut body = Vec::new();
         while !self.consume(Kind::Rb) {
-            body.push(self.parse_statement()?);
-            self.expect(Kind::Semi)?;
+            let statement = self.parse_statement()?;
+            if braced(&statement) {
+                self.consume(Kind::Semi);
+            } else {
+                self.expect(Kind::Semi)?;
+            }
+            body.push(statement);
         }
         Ok(Test { name, tags, body })
     }
 
+    /// Parses `if condition { ... }` after the `if` at `start`, with any `else if`
+    /// and `else` branches.
+    fn parse_if(&mut self, start: usize) -> Result<Expr, String> {
+        let condition = self.parse_expr()?;
+        let then = self.parse_block()?;
+        let otherwise = if !self.consume(Kind::Else) {
+            Vec::new()
+        } else if self.consume(Kind::If) {
+            vec![self.parse_if(self.index - 1)?]
+        } else {
+            self.parse_block()?
+        };
+        let kind = ExprKind::If(Box::new(condition), then, otherwise);
+        Ok(Expr::new(kind, self.span(start)))
+    }
+
+    /// Parses `for name in iterable { ... }` after the `for`, where the iterable is
+    /// an array or a `start..end` range.
+    fn parse_for(&mut self, start: usize) -> Result<Expr, String> {
+        let name = self.ident()?;
+        self.expect(Kind::In)?;
+        let from = self.index;
+        let mut iterable = self.parse_expr()?;
+        if self.consume(Kind::Range) {
+            let kind = ExprKind::Range(Box::new(iterable), Box::new(self.parse_expr()?));
+            iterable = Expr::new(kind, self.span(from));
+        }
+        self.loops += 1;
+        let body = self.parse_block();
+        self.loops -= 1;
+        let kind = ExprKind::For(name, Box::new(iterable), body?);
+        Ok(Expr::new(kind, self.span(start)))
+    }
+
+    /// Parses braced statements. The semicolon may be left out after the last
+    /// statement, whose value is the value of the block, and after an `if` or a
+    /// `for`.
+    fn parse_block(&mut self) -> Result<Vec<Expr>, String> {
+        self.expect(Kind::Lb)?;
+        let mut statements = Vec::new();
+        while !self.consume(Kind::Rb) {
+            let statement = self.parse_statement()?;
+            if braced(&statement) || self.peek_kind() == Some(Kind::Rb) {
+                self.consume(Kind::Semi);
+            } else {
+                self.expect(Kind::Semi)?;
+            }
+            statements.push(statement);
+        }
+        Ok(statements)
+    }
+
     fn parse_tags(&mut self) -> Result<Vec<String>, String> {
         let mut tags = Vec::new();
         while self.consume(Kind::Tag) {
@@ -224,15 +466,39 @@ impl Parser {
     }
 
     fn parse_statement(&mut self) -> Result<Expr, String> {
-        if self.consume(Kind::Let) {
+        let start = self.index;
+        let kind = if self.consume(Kind::Let) {
             let name = self.ident()?;
             self.expect(Kind::Assign)?;
-            return Ok(Expr::Let(name, Box::new(self.parse_expr()?)));
-        }
-        if self.consume(Kind::Expect) {
-            return Ok(Expr::Expect(Box::new(self.parse_expr()?)));
-        }
-        self.parse_expr()
+            ExprKind::Let(name, Box::new(self.parse_expr()?))
+        } else if self.consume(Kind::Expect) {
+            ExprKind::Expect(Box::new(self.parse_expr()?))
+        } else if self.consume(Kind::If) {
+            return self.parse_if(start);
+        } else if self.consume(Kind::For) {
+            return self.parse_for(start);
+        } else if self.consume(Kind::Return) {
+            if !self.function {
+                return Err(self.error_at(start, "return outside of a function"));
+            }
+            let value = match self.peek_kind() {
+                Some(Kind::Semi | Kind::Rb) => Expr::new(ExprKind::Null, self.span(start)),
+                _ => self.parse_expr()?,
+            };
+            ExprKind::Return(Box::new(value))
+        } else if self.consume(Kind::Break) || self.consume(Kind::Continue) {
+            let kind = match self.tokens[start].kind {
+                Kind::Break => ExprKind::Break,
+                _ => ExprKind::Continue,
+            };
+            if self.loops == 0 {
+                return Err(self.error_at(start, format!("{kind} outside of a loop")));
+            }
+            kind
+        } else {
+            return self.parse_expr();
+        };
+        Ok(Expr::new(kind, self.span(start)))
     }
 
     fn parse_expr(&mut self) -> Result<Expr, String> {
@@ -240,60 +506,63 @@ impl Parser {
     }
 
     fn parse_binary(&mut self, minimum: u8) -> Result<Expr, String> {
+        let start = self.index;
         let mut left = self.parse_primary()?;
         loop {
-            left = match self.peek_kind() {
+            let kind = match self.peek_kind() {
                 Some(Kind::Dot) => {
                     self.next();
-                    Expr::Field(Box::new(left), self.ident()?)
+                    ExprKind::Field(Box::new(left), self.ident()?)
                 }
                 Some(Kind::Lp) => self.parse_call(left)?,
                 Some(Kind::Ls) => {
                     self.next();
                     let index = self.parse_expr()?;
                     self.expect(Kind::Rs)?;
-                    Expr::Index(Box::new(left), Box::new(index))
+                    ExprKind::Index(Box::new(left), Box::new(index))
                 }
                 Some(kind) if precedence(&kind) >= minimum && precedence(&kind) > 0 => {
                     let token = self.next().unwrap();
                     let rule = precedence(&token.kind);
                     let right = self.parse_binary(rule + 1)?;
-                    Expr::Binary(token, Box::new(left), Box::new(right))
+                    ExprKind::Binary(token, Box::new(left), Box::new(right))
                 }
                 _ => return Ok(left),
             };
+            left = Expr::new(kind, self.span(start));
         }
     }
 
     fn parse_primary(&mut self) -> Result<Expr, String> {
+        let start = self.index;
         let token = self
             .next()
             .ok_or_else(|| "unexpected end of input".to_string())?;
-        match token.kind {
-            Kind::Ident | Kind::Env | Kind::Api => Ok(Expr::Ident(token.lite)),
-            Kind::True => Ok(Expr::Boolean(true)),
-            Kind::False => Ok(Expr::Boolean(false)),
-            Kind::Null => Ok(Expr::Null),
+        let kind = match token.kind {
+            Kind::Ident | Kind::Env | Kind::Api => ExprKind::Ident(token.lite),
+            Kind::True => ExprKind::Boolean(true),
+            Kind::False => ExprKind::Boolean(false),
+            Kind::Null => ExprKind::Null,
             Kind::Integer => token
                 .lite
                 .parse()
-                .map(Expr::Integer)
-                .map_err(|_| self.error("invalid integer")),
+                .map(ExprKind::Integer)
+                .map_err(|_| self.error_at(start, "invalid integer"))?,
             Kind::Float => token
                 .lite
                 .parse()
-                .map(Expr::Float)
-                .map_err(|_| self.error("invalid float")),
-            Kind::String => Ok(Expr::String(token.lite)),
-            Kind::Raw => Ok(Expr::Raw(token.lite)),
+                .map(ExprKind::Float)
+                .map_err(|_| self.error_at(start, "invalid float"))?,
+            Kind::String => ExprKind::String(token.lite),
+            Kind::Raw => ExprKind::Raw(token.lite),
             Kind::Not | Kind::Sub => {
                 let value = self.parse_binary(11)?;
-                Ok(Expr::Unary(token, Box::new(value)))
+                ExprKind::Unary(token, Box::new(value))
             }
             Kind::Lp => {
                 let expr = self.parse_expr()?;
                 self.expect(Kind::Rp)?;
-                Ok(Expr::Paren(Box::new(expr)))
+                ExprKind::Paren(Box::new(expr))
             }
             Kind::Ls => {
                 let mut values = Vec::new();
@@ -304,14 +573,16 @@ impl Parser {
                         break;
                     }
                 }
-                Ok(Expr::Array(values))
+                ExprKind::Array(values)
             }
-            Kind::Lb => Ok(Expr::Map(self.parse_fields_after_open()?)),
-            _ => Err(self.error(format!("unexpected token {token}"))),
-        }
+            Kind::Lb => ExprKind::Map(self.parse_fields_after_open()?),
+            Kind::If => return self.parse_if(start),
+            _ => return Err(self.error_at(start, format!("unexpected token {token}"))),
+        };
+        Ok(Expr::new(kind, self.span(start)))
     }
 
-    fn parse_call(&mut self, function: Expr) -> Result<Expr, String> {
+    fn parse_call(&mut self, function: Expr) -> Result<ExprKind, String> {
         self.expect(Kind::Lp)?;
         let mut arguments = Vec::new();
         while !self.consume(Kind::Rp) {
@@ -321,15 +592,15 @@ impl Parser {
                 break;
             }
         }
-        Ok(Expr::Call(Box::new(function), arguments))
+        Ok(ExprKind::Call(Box::new(function), arguments))
     }
 
     fn parse_pairs(&mut self) -> Result<Vec<(Expr, Expr)>, String> {
         self.expect(Kind::Lb)?;
-        let fields = self.parse_fields_after_open()?;
-        Ok(fields
+        let entries = self.parse_entries()?;
+        Ok(entries
             .into_iter()
-            .map(|(key, value)| (Expr::String(key), value))
+            .map(|(key, span, value)| (Expr::new(ExprKind::String(key), span), value))
             .collect())
     }
 
@@ -339,22 +610,33 @@ impl Parser {
     }
 
     fn parse_fields_after_open(&mut self) -> Result<Vec<(String, Expr)>, String> {
+        let entries = self.parse_entries()?;
+        Ok(entries
+            .into_iter()
+            .map(|(key, _, value)| (key, value))
+            .collect())
+    }
+
+    /// Parses object entries after the opening brace, with where each key was
+    /// written.
+    fn parse_entries(&mut self) -> Result<Vec<(String, Span, Expr)>, String> {
         let mut fields = Vec::new();
         while !self.consume(Kind::Rb) {
-            let key = match self.next() {
+            let (key, span) = match self.peek() {
                 Some(Token {
                     kind: Kind::Ident | Kind::String,
                     lite,
-                    ..
-                }) => lite,
+                    span,
+                }) => (lite.clone(), span.clone()),
                 Some(token) => return Err(self.error(format!("invalid field name {token}"))),
                 None => return Err("unterminated object".to_string()),
             };
+            self.index += 1;
             self.expect(Kind::Colon)?;
-            if fields.iter().any(|(existing, _)| existing == &key) {
-                return Err(self.error(format!("duplicate field '{key}'")));
+            if fields.iter().any(|(existing, _, _)| existing == &key) {
+                return Err(self.error_at(self.index - 2, format!("duplicate field '{key}'")));
             }
-            fields.push((key, self.parse_expr()?));
+            fields.push((key, span, self.parse_expr()?));
             if !self.consume(Kind::Comma) {
                 self.expect(Kind::Rb)?;
                 break;
@@ -364,20 +646,27 @@ impl Parser {
     }
 
     fn ident(&mut self) -> Result<String, String> {
-        match self.next() {
+        match self.peek() {
             Some(Token {
                 kind: Kind::Ident,
                 lite,
                 ..
-            }) => Ok(lite),
+            }) => {
+                let lite = lite.clone();
+                self.index += 1;
+                Ok(lite)
+            }
             Some(token) => Err(self.error(format!("expected identifier, found {token}"))),
             None => Err("expected identifier, found end of input".to_string()),
         }
     }
 
     fn expect(&mut self, kind: Kind) -> Result<(), String> {
-        match self.next() {
-            Some(token) if token.kind == kind => Ok(()),
+        match self.peek() {
+            Some(token) if token.kind == kind => {
+                self.index += 1;
+                Ok(())
+            }
             Some(token) => Err(self.error(format!("expected {kind:?}, found {token}"))),
             None => Err(format!("expected {kind:?}, found end of input")),
         }
@@ -407,14 +696,35 @@ impl Parser {
     }
 
     fn error(&self, message: impl AsRef<str>) -> String {
-        match self.peek() {
-            Some(token) => format!(
-                "{}:{}: {}",
-                token.span.start,
-                token.span.end,
-                message.as_ref()
-            ),
-            None => message.as_ref().to_owned(),
+        self.error_at(self.index, message)
+    }
+
+    /// Reports an error at the token with the given index.
+    fn error_at(&self, index: usize, message: impl AsRef<str>) -> String {
+        self.failure.set(Some(index));
+        message.as_ref().to_owned()
+    }
+
+    /// The span from the token at `start` through the last token consumed.
+    fn span(&self, start: usize) -> Span {
+        let last = self.index.saturating_sub(1).max(start);
+        match (self.tokens.get(start), self.tokens.get(last)) {
+            (Some(first), Some(last)) => Span {
+                file: self.file.clone(),
+                start: first.span.start,
+                end: last.span.end,
+            },
+            _ => self.end(),
+        }
+    }
+
+    /// The span just after the last character of the text.
+    fn end(&self) -> Span {
+        let end = self.file.text.trim_end().len();
+        Span {
+            file: self.file.clone(),
+            start: end,
+            end,
         }
     }
 }
@@ -494,6 +804,11 @@ fn string_ends(text: &str) -> HashMap<usize, usize> {
     ends
 }
 
+/// Whether a statement ends with a block, so no semicolon needs to follow it.
+fn braced(statement: &Expr) -> bool {
+    matches!(statement.kind, ExprKind::If(..) | ExprKind::For(..))
+}
+
 fn precedence(kind: &Kind) -> u8 {
     match kind {
         Kind::Lo => 1,
@@ -553,11 +868,147 @@ mod tests {
         let source = Parser::new("expect !false; let value = -1 * 2;")
             .parse()
             .unwrap();
-        assert!(matches!(source.exprs[0], Expr::Expect(_)));
-        let Expr::Let(_, value) = &source.exprs[1] else {
+        assert!(matches!(source.exprs[0].kind, ExprKind::Expect(_)));
+        let ExprKind::Let(_, value) = &source.exprs[1].kind else {
+            panic!("expected let")
+        };
+        assert!(matches!(value.kind, ExprKind::Binary(_, _, _)));
+    }
+
+    #[test]
+    fn parses_if_statements_and_expressions() {
+        let source = Parser::new(
+            r#"test flow {
+                if a { expect b; } else if c { let d = 1; expect d == 1; } else { expect e }
+                let f = if g { 1 } else { 2 };
+                expect f == 1;
+            }"#,
+        )
+        .parse()
+        .unwrap();
+        let body = &source.test("flow").unwrap().body;
+        assert_eq!(body.len(), 3);
+        assert_eq!(
+            body[0].to_string(),
+            "if a { expect b; } else if c { let d = 1; expect d == 1; } else { expect e; }"
+        );
+        let ExprKind::Let(_, value) = &body[1].kind else {
             panic!("expected let")
         };
-        assert!(matches!(value.as_ref(), Expr::Binary(_, _, _)));
+        assert!(matches!(&value.kind, ExprKind::If(_, then, otherwise)
+            if then.len() == 1 && otherwise.len() == 1));
+        let error = Parser::new("test flow { if a { expect b expect c } }")
+            .parse()
+            .unwrap_err();
+        assert!(error.contains("expected Semi"), "{error}");
+    }
+
+    #[test]
+    fn parses_for_loops_over_arrays_and_ranges() {
+        let source = Parser::new(
+            r#"test loops {
+                for item in response.json.items { if item.skip { continue } expect item.id != null; }
+                for i in 0..n + 1 { if i == 3 { break; } }
+                expect true;
+            }"#,
+        )
+        .parse()
+        .unwrap();
+        let body = &source.test("loops").unwrap().body;
+        assert_eq!(body.len(), 3);
+        assert_eq!(
+            body[0].to_string(),
+            "for item in response.json.items { if item.skip { continue; }; expect item.id != null; }"
+        );
+        let ExprKind::For(name, iterable, _) = &body[1].kind else {
+            panic!("expected for")
+        };
+        assert_eq!(name, "i");
+        assert!(matches!(&iterable.kind, ExprKind::Range(start, end)
+            if start.to_string() == "0" && end.to_string() == "n + 1"));
+        let error = Parser::new("test flow { if a { break; } }")
+            .parse()
+            .unwrap_err();
+        assert!(error.contains("break outside of a loop"), "{error}");
+    }
+
+    #[test]
+    fn parses_function_declarations() {
+        let source = Parser::new(
+            r#"fn token(user: string, attempts: int) { let value = user; return value; }
+            fn empty() { if true { return; } }
+            test flow { expect token("a", 1) == "a"; }"#,
+        )
+        .parse()
+        .unwrap();
+        let function = source.function("token").unwrap();
+        assert_eq!(
+            function
+                .params
+                .iter()
+                .map(|param| (param.name.as_str(), param.kind.as_str()))
+                .collect::<Vec<_>>(),
+            [("user", "string"), ("attempts", "int")]
+        );
+        assert_eq!(function.body[1].to_string(), "return value");
+        assert_eq!(
+            source.function("empty").unwrap().body[0].to_string(),
+            "if true { return null; }"
+        );
+        for (text, message) in [
+            ("fn twice() {} fn twice() {}", "duplicate function"),
+            ("fn typed(a: text) {}", "unknown parameter type 'text'"),
+            ("test flow { return 1; }", "return outside of a function"),
+        ] {
+            let error = Parser::new(text).parse().unwrap_err();
+            assert!(error.contains(message), "{error}");
+        }
+    }
+
+    #[test]
+    fn parses_imports_with_optional_aliases() {
+        let source = Parser::new(r#"import "../common/auth.fan" as auth; import "base.fan""#)
+            .parse()
+            .unwrap();
+        let imports = source
+            .imports
+            .iter()
+            .map(|import| (import.path.as_str(), import.alias.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            imports,
+            [("../common/auth.fan", Some("auth")), ("base.fan", None)]
+        );
+        let error = Parser::new("import auth").parse().unwrap_err();
+        assert!(error.contains("expected import path"), "{error}");
+    }
+
+    #[test]
+    fn reports_parse_errors_with_file_line_and_column() {
+        let error = Parser::named("flows/login.fan", "test login {\n    let = 1;\n}")
+            .parse()
+            .unwrap_err();
+        assert_eq!(
+            error,
+            [
+                "expected identifier, found =",
+                " --> flows/login.fan:2:9",
+                "  |",
+                "2 |     let = 1;",
+                "  |         ^",
+            ]
+            .join("\n")
+        );
+        let error = Parser::new("test login { expect 1").parse().unwrap_err();
+        assert!(error.contains("--> <input>:1:22"), "{error}");
+        let source = Parser::new("let a = 1;\nexpect a + 1 == 2;")
+            .parse()
+            .unwrap();
+        let Expr { span, .. } = &source.exprs[1];
+        assert_eq!(
+            (span.start, span.end, span.file.name.as_str()),
+            (11, 27, "<input>")
+        );
     }
 
     #[test]
@@ -568,12 +1019,12 @@ mod tests {
         .parse()
         .unwrap();
         assert_eq!(source.exprs.len(), 2);
-        let Expr::Let(_, value) = &source.exprs[1] else {
+        let ExprKind::Let(_, value) = &source.exprs[1].kind else {
             panic!("expected let")
         };
         assert_eq!(
-            value.as_ref(),
-            &Expr::String(r#"prefix \("quoted") suffix"#.into())
+            value.kind,
+            ExprKind::String(r#"prefix \("quoted") suffix"#.into())
         );
     }
 
@@ -642,4 +1093,24 @@ mod tests {
             .unwrap_err();
         assert!(field.contains("duplicate field 'host'"), "{field}");
     }
+
+    #[test]
+    fn parses_api_and_request_timeouts() {
+        let source = Parser::new(
+            r#"api slow { scheme: http, host: "localhost", timeout: { connect: 500, total: 3000 },
+                get() { method: GET, path: "/", timeout: { read: 1000 } }
+            }"#,
+        )
+        .parse()
+        .unwrap();
+        let api = source.apis.get("slow").unwrap();
+        assert_eq!(api.timeout.len(), 2);
+        assert_eq!(api.requests["get"].timeout[0].0, Timeout::Read);
+
+        let error =
+            Parser::new(r#"api slow { scheme: http, host: "localhost", timeout: { idle: 500 } }"#)
+                .parse()
+                .unwrap_err();
+        assert!(error.contains("unknown timeout 'idle'"), "{error}");
+    }
 }
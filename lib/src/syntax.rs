@@ -1,11 +1,19 @@
+use super::Span;
 use super::Token;
 use super::Value;
 use crate::api::Apis;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display, Formatter, Result};
 
+/// An expression and where it was written.
 #[derive(Clone, PartialEq)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Clone, PartialEq)]
+pub enum ExprKind {
     Null,
     Integer(i64),
     Float(f64),
@@ -23,29 +31,56 @@ pub enum Expr {
     Binary(Token, Box<Expr>, Box<Expr>),
     Paren(Box<Expr>),
     Call(Box<Expr>, Vec<Expr>),
+    /// A condition with the statements run when it holds and those run when it
+    /// does not; `else if` is an `If` alone in the second block.
+    If(Box<Expr>, Vec<Expr>, Vec<Expr>),
+    /// The integers from the first bound up to but not including the second.
+    Range(Box<Expr>, Box<Expr>),
+    /// A loop binding each element of an array or range to a name in turn.
+    For(String, Box<Expr>, Vec<Expr>),
+    Break,
+    Continue,
+    /// Leaves the enclosing function with a value, `null` when none is given.
+    Return(Box<Expr>),
 }
 
 impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+
     pub fn eval(&self) -> Value {
-        match self {
-            Self::Integer(value) => Value::Integer(*value),
-            Self::Null => Value::Null,
-            Self::Float(value) => Value::Float(*value),
-            Self::Boolean(value) => Value::Boolean(*value),
-            Self::String(value) | Self::Raw(value) => Value::String(value.clone()),
-            Self::Map(values) => Value::Map(
+        match &self.kind {
+            ExprKind::Integer(value) => Value::Integer(*value),
+            ExprKind::Null => Value::Null,
+            ExprKind::Float(value) => Value::Float(*value),
+            ExprKind::Boolean(value) => Value::Boolean(*value),
+            ExprKind::String(value) | ExprKind::Raw(value) => Value::String(value.clone()),
+            ExprKind::Map(values) => Value::Map(
                 values
                     .iter()
                     .map(|(key, value)| (key.clone(), value.eval()))
                     .collect(),
             ),
-            Self::Array(values) => Value::Array(values.iter().map(Self::eval).collect()),
+            ExprKind::Array(values) => Value::Array(values.iter().map(Self::eval).collect()),
             _ => Value::Null,
         }
     }
 }
 
 impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        Display::fmt(&self.kind, f)
+    }
+}
+
+impl Debug for Expr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        Display::fmt(&self.kind, f)
+    }
+}
+
+impl Display for ExprKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
             Self::Integer(value) => write!(f, "{value}"),
@@ -73,16 +108,38 @@ impl Display for Expr {
             Self::Binary(token, left, right) => write!(f, "{left} {token} {right}"),
             Self::Paren(value) => write!(f, "({value})"),
             Self::Call(function, arguments) => write!(f, "{function}({})", join(arguments, ", ")),
+            Self::If(condition, then, otherwise) => {
+                write!(f, "if {condition} {}", block(then))?;
+                match &otherwise[..] {
+                    [] => Ok(()),
+                    [branch] if matches!(branch.kind, Self::If(..)) => write!(f, " else {branch}"),
+                    otherwise => write!(f, " else {}", block(otherwise)),
+                }
+            }
+            Self::Range(start, end) => write!(f, "{start}..{end}"),
+            Self::For(name, iterable, body) => {
+                write!(f, "for {name} in {iterable} {}", block(body))
+            }
+            Self::Break => write!(f, "break"),
+            Self::Continue => write!(f, "continue"),
+            Self::Return(value) => write!(f, "return {value}"),
         }
     }
 }
 
-impl Debug for Expr {
+impl Debug for ExprKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         Display::fmt(self, f)
     }
 }
 
+fn block(statements: &[Expr]) -> String {
+    match statements {
+        [] => "{}".into(),
+        statements => format!("{{ {}; }}", join(statements, "; ")),
+    }
+}
+
 fn join(values: &[Expr], separator: &str) -> String {
     values
         .iter()
@@ -104,11 +161,28 @@ pub struct Test {
     pub body: Vec<Expr>,
 }
 
+/// A function declared with `fn`, called with one value per typed parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<crate::api::Parameter>,
+    pub body: Vec<Expr>,
+}
+
+/// Another file whose declarations are brought in, under `alias` when one is given.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Import {
+    pub path: String,
+    pub alias: Option<String>,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum Declaration {
     Environment(Environment),
-    Api(crate::api::Api),
+    Api(Box<crate::api::Api>),
+    Function(Function),
     Test(Test),
+    Import(Import),
 }
 
 #[derive(Clone, Debug, Default)]
@@ -116,8 +190,12 @@ pub struct Source {
     pub declarations: Vec<Declaration>,
     pub environments: HashMap<String, Environment>,
     pub apis: Apis,
+    pub functions: HashMap<String, Function>,
     pub tests: HashMap<String, Test>,
+    pub imports: Vec<Import>,
     pub exprs: Vec<Expr>,
+    /// The file each merged declaration came from, by its kind and name
+    pub origins: HashMap<String, String>,
 }
 
 impl Source {
@@ -130,12 +208,100 @@ impl Source {
         self.declarations.extend(source.declarations);
         self.environments.extend(source.environments);
         self.apis.extend(source.apis);
+        self.functions.extend(source.functions);
         self.tests.extend(source.tests);
         self.exprs.extend(source.exprs);
         length
     }
 
+    /// Adds the declarations of another file, failing on a name that is already
+    /// declared, with the file that declared it first. Under a namespace only APIs
+    /// and functions can be added, named with the namespace and a dot in front.
+    pub fn merge(
+        &mut self,
+        source: Self,
+        namespace: &str,
+        file: &str,
+    ) -> std::result::Result<(), String> {
+        if !namespace.is_empty() {
+            let unscoped = [
+                ("environments", source.environments.is_empty()),
+                ("tests", source.tests.is_empty()),
+                ("top-level statements", source.exprs.is_empty()),
+            ];
+            if let Some((kind, _)) = unscoped.iter().find(|(_, empty)| !empty) {
+                return Err(format!(
+                    "{kind} cannot be imported as '{namespace}'; import the file without an alias"
+                ));
+            }
+        }
+        let qualify = |name: &str| match namespace {
+            "" => name.to_owned(),
+            namespace => format!("{namespace}.{name}"),
+        };
+        let mut declare = |kind: &str, name: &str| match self
+            .origins
+            .insert(format!("{kind} '{name}'"), file.to_owned())
+        {
+            Some(other) => Err(format!(
+                "duplicate {kind} '{name}', also declared in {other}"
+            )),
+            None => Ok(()),
+        };
+        for declaration in &source.declarations {
+            match declaration {
+                Declaration::Environment(environment) => declare("environment", &environment.name)?,
+                Declaration::Api(api) => declare("api", &qualify(&api.name))?,
+                Declaration::Function(function) => declare("function", &qualify(&function.name))?,
+                Declaration::Test(test) => declare("test", &test.name)?,
+                Declaration::Import(_) => {}
+            }
+        }
+        for declaration in source.declarations {
+            match declaration {
+                Declaration::Environment(environment) => {
+                    if self.environments.contains_key(&environment.name) {
+                        return Err(format!("duplicate environment '{}'", environment.name));
+                    }
+                    self.environments
+                        .insert(environment.name.clone(), environment.clone());
+                    self.declarations
+                        .push(Declaration::Environment(environment));
+                }
+                Declaration::Api(mut api) => {
+                    api.name = qualify(&api.name);
+                    self.apis.insert(api.as_ref().clone())?;
+                    self.declarations.push(Declaration::Api(api));
+                }
+                Declaration::Function(mut function) => {
+                    function.name = qualify(&function.name);
+                    if self.functions.contains_key(&function.name) {
+                        return Err(format!("duplicate function '{}'", function.name));
+                    }
+                    self.functions
+                        .insert(function.name.clone(), function.clone());
+                    self.declarations.push(Declaration::Function(function));
+                }
+                Declaration::Test(test) => {
+                    if self.tests.contains_key(&test.name) {
+                        return Err(format!("duplicate test '{}'", test.name));
+                    }
+                    self.tests.insert(test.name.clone(), test.clone());
+                    self.declarations.push(Declaration::Test(test));
+                }
+                // imports are merged by whoever loads the files
+                Declaration::Import(_) => {}
+            }
+        }
+        self.exprs.extend(source.exprs);
+        Ok(())
+    }
+
     pub fn test(&self, name: &str) -> Option<&Test> {
         self.tests.get(name)
     }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.get(name)
+    }
 }
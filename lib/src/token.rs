@@ -1,6 +1,7 @@
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
+use std::sync::Arc;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Kind {
@@ -15,17 +16,28 @@ pub enum Kind {
     Null,    // null
     Illegal, // illegal
     // keyword
-    Env,    // env
-    Api,    // api
-    Test,   // test
-    Let,    // let
-    Expect, // expect
+    Env,      // env
+    Api,      // api
+    Test,     // test
+    Let,      // let
+    Expect,   // expect
+    If,       // if
+    Else,     // else
+    For,      // for
+    In,       // in
+    Break,    // break
+    Continue, // continue
+    Fn,       // fn
+    Return,   // return
+    Import,   // import
+    As,       // as
     // delimiter
     Assign, // =
     Comma,  // ,
     Semi,   // ;
     Colon,  // :
     Dot,    // .
+    Range,  // ..
     Tag,    // @tag
     // operator
     Add, // +
@@ -56,12 +68,56 @@ pub enum Kind {
     Rs, // ]
 }
 
+/// A parsed text and the name it is reported under, kept so errors can quote it.
+#[derive(Debug, PartialEq, Eq)]
+pub struct File {
+    pub name: String,
+    pub text: String,
+}
+
+/// Where a token or expression was written: byte offsets into a file, the end
+/// inclusive.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Span {
+    pub file: Arc<File>,
     pub start: usize,
     pub end: usize,
 }
 
+impl Span {
+    /// Formats a message with the file, line and column the span starts at and
+    /// the line itself, the span underlined with carets.
+    pub fn render(&self, message: &str) -> String {
+        let text = self.file.text.as_str();
+        let start = (0..=self.start.min(text.len()))
+            .rev()
+            .find(|&index| text.is_char_boundary(index))
+            .unwrap_or_default();
+        let line_start = text[..start].rfind('\n').map_or(0, |index| index + 1);
+        let line_end = text[start..]
+            .find('\n')
+            .map_or(text.len(), |index| start + index);
+        let line = text[..start].matches('\n').count() + 1;
+        let before = &text[line_start..start];
+        let column = before.chars().count() + 1;
+        let width = text
+            .get(start..(self.end + 1).clamp(start, line_end))
+            .map_or(0, |span| span.chars().count())
+            .max(1);
+        let indent: String = before
+            .chars()
+            .map(|character| if character == '\t' { '\t' } else { ' ' })
+            .collect();
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "{message}\n{gutter}--> {}:{line}:{column}\n{gutter} |\n{line} | {}\n{gutter} | {indent}{}",
+            self.file.name,
+            &text[line_start..line_end],
+            "^".repeat(width)
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Token {
     pub kind: Kind,
@@ -12,21 +12,98 @@ pub struct Request {
     pub url: String,
     pub headers: Vec<Header>,
     pub body: Option<Content>,
+    pub timeout: Timeout,
+    pub redirects: usize,
+    /// Whether redirects may lead from https to plain http
+    pub downgrade: bool,
+    /// The jar the `Cookie` header was built from. A transport that follows
+    /// redirects stores each hop's cookies in it and rebuilds the header per hop.
+    pub cookies: Option<crate::Jar>,
+    pub version: String,
+    pub proxy: Option<String>,
+    pub tls: Tls,
+    pub resolve: Vec<(String, String)>,
+    pub socket: Option<String>,
+    pub events: Events,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Tls {
+    pub ca: Option<String>,
+    pub cert: Option<String>,
+    pub key: Option<String>,
+    pub insecure: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Timeout {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub total: Option<Duration>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Events {
+    pub count: Option<usize>,
+    pub until: Option<String>,
+    pub timeout: Option<Duration>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Content {
     Text(String),
     File(String),
+    Parts(Vec<Part>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Part {
+    pub name: String,
+    pub content: Content,
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Response {
+    pub url: String,
     pub version: String,
     pub status: u16,
     pub reason: String,
     pub headers: Vec<Header>,
-    pub body: String,
+    pub body: Vec<u8>,
+    pub trailers: Vec<Header>,
+    pub encoding: String,
+    pub transferred: usize,
+    pub reused: bool,
+    pub tls: Option<TlsSession>,
+    pub redirects: Vec<Response>,
+    pub events: Vec<Event>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Event {
+    pub id: String,
+    pub event: String,
+    pub data: String,
+    pub retry: Option<u64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TlsSession {
+    pub version: String,
+    pub cipher: String,
+    pub alpn: String,
+    pub certificates: Vec<Certificate>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Certificate {
+    pub subject: String,
+    pub issuer: String,
+    pub names: Vec<String>,
+    pub not_before: i64,
+    pub not_after: i64,
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -44,9 +121,120 @@ pub struct Timing {
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct Result {
     pub request: Request,
+    /// The requests sent to follow redirects, one after each response in
+    /// `response.redirects`
+    pub followed: Vec<Request>,
     pub response: Response,
     pub timing: Timing,
-    pub error: String,
+    pub error: Option<Error>,
+}
+
+/// Why a request or WebSocket operation failed without a response.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+/// The class of a transport failure, which scripts see as `error.kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The request or its settings could not be used as given
+    Invalid,
+    /// The host name did not resolve
+    Dns,
+    /// The server refused the connection
+    Refused,
+    /// The connection failed for another reason
+    Connect,
+    ConnectTimeout,
+    ReadTimeout,
+    /// The total timeout passed
+    Timeout,
+    /// The TLS configuration or handshake failed
+    Tls,
+    /// The proxy was invalid or refused to tunnel
+    Proxy,
+    /// The peer broke the HTTP/2 or WebSocket protocol
+    Protocol,
+    /// A redirect ran past the hop limit or would downgrade https to http
+    Redirect,
+    /// The connection or WebSocket was closed
+    Closed,
+    /// The transport does not support the operation
+    Unsupported,
+    /// A replaying transport has no recording of the request
+    Unmatched,
+    Io,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Invalid => "invalid",
+            ErrorKind::Dns => "dns",
+            ErrorKind::Refused => "refused",
+            ErrorKind::Connect => "connect",
+            ErrorKind::ConnectTimeout => "connect_timeout",
+            ErrorKind::ReadTimeout => "read_timeout",
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Proxy => "proxy",
+            ErrorKind::Protocol => "protocol",
+            ErrorKind::Redirect => "redirect",
+            ErrorKind::Closed => "closed",
+            ErrorKind::Unsupported => "unsupported",
+            ErrorKind::Unmatched => "unmatched",
+            ErrorKind::Io => "io",
+        }
+    }
+}
+
+impl std::str::FromStr for ErrorKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match name {
+            "invalid" => ErrorKind::Invalid,
+            "dns" => ErrorKind::Dns,
+            "refused" => ErrorKind::Refused,
+            "connect" => ErrorKind::Connect,
+            "connect_timeout" => ErrorKind::ConnectTimeout,
+            "read_timeout" => ErrorKind::ReadTimeout,
+            "timeout" => ErrorKind::Timeout,
+            "tls" => ErrorKind::Tls,
+            "proxy" => ErrorKind::Proxy,
+            "protocol" => ErrorKind::Protocol,
+            "redirect" => ErrorKind::Redirect,
+            "closed" => ErrorKind::Closed,
+            "unsupported" => ErrorKind::Unsupported,
+            "unmatched" => ErrorKind::Unmatched,
+            "io" => ErrorKind::Io,
+            name => return Err(format!("unknown error kind '{name}'")),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Frame {
+    Text(String),
+    Binary(Vec<u8>),
+    Close { code: Option<u16>, reason: String },
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
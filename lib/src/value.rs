@@ -21,8 +21,11 @@ pub enum Value {
     Float(f64),
     Boolean(bool),
     String(String),
+    Bytes(Vec<u8>),
     Array(Vec<Value>),
     Map(HashMap<String, Value>),
+    /// An open WebSocket, by the id its transport gave it
+    Socket(u64),
 }
 
 impl Value {
@@ -32,11 +35,19 @@ impl Value {
 
     pub fn to_json(&self) -> String {
         match self {
-            Self::Null => "null".into(),
+            Self::Null | Self::Socket(_) => "null".into(),
             Self::Integer(value) => value.to_string(),
             Self::Float(value) => value.to_string(),
             Self::Boolean(value) => value.to_string(),
             Self::String(value) => format!("\"{}\"", escape_json(value)),
+            Self::Bytes(bytes) => format!(
+                "[{}]",
+                bytes
+                    .iter()
+                    .map(u8::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
             Self::Array(values) => format!(
                 "[{}]",
                 values
@@ -73,8 +84,10 @@ impl Display for Value {
             Value::Float(float) => write!(f, "{float}"),
             Value::Boolean(boolean) => write!(f, "{boolean}"),
             Value::String(string) => write!(f, "{string}"),
+            Value::Bytes(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
             Value::Array(items) => write!(f, "{items:?}"),
             Value::Map(pairs) => write!(f, "{pairs:?}"),
+            Value::Socket(id) => write!(f, "websocket {id}"),
         }
     }
 }
@@ -87,8 +100,10 @@ impl Debug for Value {
             Value::Float(float) => write!(f, "{float}"),
             Value::Boolean(boolean) => write!(f, "{boolean}"),
             Value::String(string) => write!(f, "\"{string}\""),
+            Value::Bytes(bytes) => write!(f, "<{} bytes>", bytes.len()),
             Value::Array(items) => write!(f, "{items:?}"),
             Value::Map(pairs) => write!(f, "{pairs:?}"),
+            Value::Socket(id) => write!(f, "<websocket {id}>"),
         }
     }
 }